@@ -0,0 +1,80 @@
+extern crate dawr;
+
+use dawr::clock::*;
+use dawr::synth::*;
+use dawr::events::*;
+use dawr::device::*;
+use dawr::effects::*;
+use dawr::conversions::*;
+use dawr::sampler::*;
+use dawr::files;
+use std::rc::Rc;
+use std::time::Instant;
+
+// Renders a graph shaped like demotrack's (drum samplers plus detuned unison synths, all summed
+// by mixers) and reports how much faster than real time it runs. Run it with --release.
+fn main() {
+    let c = Clock::new();
+    let bpm = 160.0;
+    let bars = 16;
+
+    let mut drumevents = Vec::<(u64, SamplerEvent)>::new();
+    let mut notes = Vec::<(u64, PolyNoteEvent)>::new();
+
+    let mut m = TimeCalculator::for_clock(bpm, &c);
+    for _bar in 0..bars {
+        for eighth in 0..8 {
+            drumevents.push((m.add_eighths(eighth as f64).time(), SamplerEvent::Play));
+        }
+        for (id, ratio) in [1.0, 1.25, 1.5, 1.875].iter().enumerate() {
+            notes.push((m.time(), PolyNoteEvent::NoteOn(id as u32, 220.0 * ratio)));
+        }
+        for id in 0..4 {
+            notes.push((m.add_quarters(3.0).time(), PolyNoteEvent::NoteOff(id)));
+        }
+        m = m.add_bars(1.0);
+    }
+
+    let drum_events = EventSource::new(drumevents, c.clone());
+    let mut sources = Vec::<Rc<StereoEmitter>>::new();
+    for name in ["Kick", "Snare", "HiHat", "HiHat2", "808"].iter() {
+        let (left, right) = files::load_wav_to_stereo(&format!("sounds/{}.wav", name));
+        sources.push(Gain::new(
+            c.clone(),
+            Sampler::new(c.clone(), drum_events.clone(), left, right),
+            ConstSignal::new(c.clone(), decibels(-6.0))
+        ));
+    }
+
+    let note_events = EventSource::new(notes, c.clone());
+    let unison = 8;
+    let mut synths = Vec::<Rc<StereoEmitter>>::new();
+    for voice in 0..unison {
+        let ratio = voice as f32 / (unison - 1) as f32;
+        synths.push(Pan::new(
+            c.clone(),
+            PolySynth::new(
+                c.clone(),
+                note_events.clone(),
+                4,
+                VoiceStealing::Oldest,
+                WaveTable::new(vec![Wave::saw()]),
+                ConstSignal::new(c.clone(), 0.99 + 0.02 * ratio),
+                ConstSignal::new(c.clone(), 0.0),
+                &|voice_events| Envelope::new(c.clone(), voice_events)
+            ),
+            ConstSignal::new(c.clone(), 1.0 - 2.0 * ratio)
+        ));
+    }
+    sources.push(Gain::new(c.clone(), Mixer::new(c.clone(), synths), ConstSignal::new(c.clone(), decibels(-20.0))));
+
+    let master = Gain::new(c.clone(), Mixer::new(c.clone(), sources), ConstSignal::new(c.clone(), decibels(-5.0)));
+
+    let length = TimeCalculator::for_clock(bpm, &c).add_bars(bars as f64);
+    let start = Instant::now();
+    dawr::render_audio(c, master, length.time() as usize);
+    let elapsed = start.elapsed();
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+    println!("Rendered {:.1}s of audio in {:.3}s ({:.1}x real time).", length.seconds(), seconds, length.seconds() / seconds);
+}
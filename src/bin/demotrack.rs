@@ -9,7 +9,6 @@ use dawr::effects::*;
 use dawr::conversions::*;
 use dawr::sampler::*;
 use dawr::files;
-use dawr::consts;
 use std::rc::Rc;
 
 use portaudio as pa;
@@ -35,12 +34,9 @@ fn run() -> Result<(), pa::Error> {
     let mut hat2events = Vec::<(u64, SamplerEvent)>::new();
     let mut bassevents = Vec::<(u64, SamplerEvent)>::new();
 
-    let mut notes_a = Vec::<(u64, NoteEvent)>::new();
-    let mut notes_b = Vec::<(u64, NoteEvent)>::new();
-    let mut notes_c = Vec::<(u64, NoteEvent)>::new();
-    let mut notes_d = Vec::<(u64, NoteEvent)>::new();
+    let mut notes = Vec::<(u64, PolyNoteEvent)>::new();
 
-    let mut m = TimeCalculator::new(160.0);
+    let mut m = TimeCalculator::for_clock(160.0, &c);
     for bar in 0..16 {
         for beat in 0..4 {
             if (bar % 2 == 0 && beat == 0) || (bar % 2 == 1 && beat == 1) || (bar % 4 == 1 && beat == 3)  {
@@ -68,64 +64,31 @@ fn run() -> Result<(), pa::Error> {
         const E1_FREQ : f32 = 41.20;
         let half_step : f32 = 2.0_f32.powf(1.0/12.0);
 
+        // Plays a four-note chord, one note id per chord tone.
+        let mut chord = |start: &TimeCalculator, end: &TimeCalculator, root: f32, intervals: [f32; 4]| {
+            for (id, interval) in intervals.iter().enumerate() {
+                notes.push((start.time(), PolyNoteEvent::NoteOn(id as u32, root * half_step.powf(*interval))));
+                notes.push((end.time(), PolyNoteEvent::NoteOff(id as u32)));
+            }
+        };
+
         match bar % 4 {
             0 => {
                 // III7
                 let shift = half_step.powf(3.0);
                 for i in 0..4 {
-                    notes_a.push((m.add_eighths(i as f64).time(), NoteEvent::NoteOn(shift*8.0*E1_FREQ)));
-                    notes_a.push((m.add_eighths(i as f64).add_sixteenths(1.0).time(), NoteEvent::NoteOff));
-
-                    notes_b.push((m.add_eighths(i as f64).time(), NoteEvent::NoteOn(shift*8.0*E1_FREQ * half_step.powf(4.0) )));
-                    notes_b.push((m.add_eighths(i as f64).add_sixteenths(1.0).time(), NoteEvent::NoteOff));
-
-                    notes_c.push((m.add_eighths(i as f64).time(), NoteEvent::NoteOn(shift*8.0*E1_FREQ * half_step.powf(7.0) )));
-                    notes_c.push((m.add_eighths(i as f64).add_sixteenths(1.0).time(), NoteEvent::NoteOff));
-
-                    notes_d.push((m.add_eighths(i as f64).time(), NoteEvent::NoteOn(shift*8.0*E1_FREQ * half_step.powf(11.0) )));
-                    notes_d.push((m.add_eighths(i as f64).add_sixteenths(1.0).time(), NoteEvent::NoteOff));
+                    chord(&m.add_eighths(i as f64), &m.add_eighths(i as f64).add_sixteenths(1.0), shift*8.0*E1_FREQ, [0.0, 4.0, 7.0, 11.0]);
                 }
             },
             2 => {
                 // i7
-                notes_a.push((m.time(), NoteEvent::NoteOn(8.0*E1_FREQ)));
-                notes_a.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_b.push((m.time(), NoteEvent::NoteOn(8.0*E1_FREQ * half_step.powf(3.0) )));
-                notes_b.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_c.push((m.time(), NoteEvent::NoteOn(8.0*E1_FREQ * half_step.powf(7.0) )));
-                notes_c.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_d.push((m.time(), NoteEvent::NoteOn(8.0*E1_FREQ * half_step.powf(10.0))));
-                notes_d.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
+                chord(&m, &m.add_quarters(2.0), 8.0*E1_FREQ, [0.0, 3.0, 7.0, 10.0]);
             },
             1 | 3 => {
                 let fifth = half_step.powf(7.0);
                 // v7
-                notes_a.push((m.add_quarters(1.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ)));
-                notes_a.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_a.push((m.add_quarters(3.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ)));
-                notes_a.push((m.add_quarters(4.0).time(), NoteEvent::NoteOff));
-
-                notes_b.push((m.add_quarters(1.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ * half_step.powf(3.0) )));
-                notes_b.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_b.push((m.add_quarters(3.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ * half_step.powf(3.0) )));
-                notes_b.push((m.add_quarters(4.0).time(), NoteEvent::NoteOff));
-
-                notes_c.push((m.add_quarters(1.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ * half_step.powf(7.0) )));
-                notes_c.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_c.push((m.add_quarters(3.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ * half_step.powf(7.0) )));
-                notes_c.push((m.add_quarters(4.0).time(), NoteEvent::NoteOff));
-
-                notes_d.push((m.add_quarters(1.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ * half_step.powf(10.0))));
-                notes_d.push((m.add_quarters(2.0).time(), NoteEvent::NoteOff));
-
-                notes_d.push((m.add_quarters(3.0).time(), NoteEvent::NoteOn(fifth*8.0*E1_FREQ * half_step.powf(10.0))));
-                notes_d.push((m.add_quarters(4.0).time(), NoteEvent::NoteOff));
+                chord(&m.add_quarters(1.0), &m.add_quarters(2.0), fifth*8.0*E1_FREQ, [0.0, 3.0, 7.0, 10.0]);
+                chord(&m.add_quarters(3.0), &m.add_quarters(4.0), fifth*8.0*E1_FREQ, [0.0, 3.0, 7.0, 10.0]);
             },
             _ => {
                 panic!("This will never happen!");
@@ -134,90 +97,138 @@ fn run() -> Result<(), pa::Error> {
         m = m.add_bars(1.0);
     }
 
-    let (kick_l, kick_r) = files::load_wav_to_stereo("sounds/Kick.wav");
-    let kick = Gain::new(
-        c.clone(),
-        Sampler::new(c.clone(), EventSource::new(kickevents, c.clone()), kick_l, kick_r),
-        ConstSignal::new(c.clone(), decibels(6.0))
-    );
+    let (kick_l, kick_r) = files::load_wav_to_stereo_at_rate("sounds/Kick.wav", c.sample_rate());
+    let kick = Sampler::new(c.clone(), EventSource::new(kickevents, c.clone()), kick_l, kick_r);
 
-    let (hat_l, hat_r) = files::load_wav_to_stereo("sounds/HiHat.wav");
+    let (hat_l, hat_r) = files::load_wav_to_stereo_at_rate("sounds/HiHat.wav", c.sample_rate());
     let hihat = Sampler::new(c.clone(), EventSource::new(hatevents, c.clone()), hat_l, hat_r);
 
-    let (hat2_l, hat2_r) = files::load_wav_to_stereo("sounds/HiHat2.wav");
+    let (hat2_l, hat2_r) = files::load_wav_to_stereo_at_rate("sounds/HiHat2.wav", c.sample_rate());
     let hihat2 = Sampler::new(c.clone(), EventSource::new(hat2events, c.clone()), hat2_l, hat2_r);
 
-    let (snare_l, snare_r) = files::load_wav_to_stereo("sounds/Snare.wav");
-    let snare = Gain::new(
-        c.clone(),
-        Sampler::new(c.clone(), EventSource::new(snareevents, c.clone()), snare_l, snare_r),
-        ConstSignal::new(c.clone(), decibels(6.0))
-    );
+    let (snare_l, snare_r) = files::load_wav_to_stereo_at_rate("sounds/Snare.wav", c.sample_rate());
+    let snare = Sampler::new(c.clone(), EventSource::new(snareevents, c.clone()), snare_l, snare_r);
 
-    let (bass_l, bass_r) = files::load_wav_to_stereo("sounds/808.wav");
-    let bass = Gain::new(
-        c.clone(),
-        Sampler::new(c.clone(), EventSource::new(bassevents, c.clone()), bass_l, bass_r),
-        ConstSignal::new(c.clone(), decibels(6.0))
-    );
+    let (bass_l, bass_r) = files::load_wav_to_stereo_at_rate("sounds/808.wav", c.sample_rate());
+    let bass = Sampler::new(c.clone(), EventSource::new(bassevents, c.clone()), bass_l, bass_r);
+
+    // The chords were pushed one note at a time, so put them in time order. The sort is stable, so
+    // a NoteOff and NoteOn at the same time stay in the order they were pushed.
+    notes.sort_by_key(|event| event.0);
+    let es = EventSource::new(notes, c.clone());
 
-    let note_channels = vec![notes_a, notes_b, notes_c, notes_d];
     let mut synths = Vec::<Rc<StereoEmitter>>::new();
 
-    for events in note_channels {
-        let es = EventSource::new(events, c.clone());
-        let voices = 4;
-        for voice in 0..voices {
-            let ratio = voice as f32 / (voices - 1) as f32;
-            let detune = 0.99 + 0.02 * ratio;
-            let pan = 1.0 - 2.0 * ratio;
-            let synth = Pan::new(
+    let unison = 4;
+    for voice in 0..unison {
+        let ratio = voice as f32 / (unison - 1) as f32;
+        let detune = 0.99 + 0.02 * ratio;
+        let pan = 1.0 - 2.0 * ratio;
+        let synth = Pan::new(
+            c.clone(),
+            PolySynth::new(
                 c.clone(),
-                MonoToStereo::new(
+                es.clone(),
+                4,
+                VoiceStealing::Oldest,
+                WaveTable::new(vec![Wave::saw()]),
+                ConstSignal::new(c.clone(), detune),
+                // Wavetable position.
+                ConstSignal::new(c.clone(), 0.0),
+                &|voice_events| Envelope::adsr(
                     c.clone(),
-                    MonoSynth::new(
-                        c.clone(),
-                        WaveTable::new(vec![Wave::saw()]),
-                        Oscillator::new(c.clone(), es.clone(), ConstSignal::new(c.clone(), detune)),
-                        // Wavetable position.
-                        ConstSignal::new(c.clone(), 0.0),
-                        Envelope::new(c.clone(), es.clone())
-                    )
-                ),
-                ConstSignal::new(c.clone(), pan)
-            );
-            synths.push(synth);
-        }
+                    voice_events,
+                    ConstSignal::new(c.clone(), 0.005),
+                    ConstSignal::new(c.clone(), 0.1),
+                    ConstSignal::new(c.clone(), 0.8),
+                    ConstSignal::new(c.clone(), 0.05),
+                    EnvelopeCurves::exponential()
+                )
+            ),
+            ConstSignal::new(c.clone(), pan)
+        );
+        synths.push(synth);
     }
 
-    let synths_mixed = Gain::new(
+    // Duck the synths whenever the kick hits.
+    let synths_mixed = Compressor::new(
         c.clone(),
         Mixer::new(c.clone(), synths),
-        ConstSignal::new(c.clone(), decibels(-10.0))
+        Some(kick.clone()),
+        CompressorSettings {
+            threshold: ConstSignal::new(c.clone(), -30.0),
+            ratio: ConstSignal::new(c.clone(), 6.0),
+            knee: ConstSignal::new(c.clone(), 6.0),
+            attack: ConstSignal::new(c.clone(), 0.002),
+            release: ConstSignal::new(c.clone(), 0.2),
+            makeup: ConstSignal::new(c.clone(), 0.0)
+        }
+    );
+
+    let channel = |name: &str, input: Rc<StereoEmitter>, gain: f32| {
+        MixerChannel::new(c.clone(), name, input, ConstSignal::new(c.clone(), decibels(gain)), ConstSignal::new(c.clone(), 0.0))
+    };
+    let mixer = ChannelMixer::new(c.clone(), vec![
+        channel("kick", kick, 0.0),
+        channel("hihat", hihat, -6.0),
+        channel("hihat2", hihat2, -6.0),
+        channel("snare", snare, 0.0),
+        channel("bass", bass, 0.0),
+        channel("synths", synths_mixed, -10.0)
+    ]);
+
+    let reverb = Reverb::new(
+        c.clone(),
+        AuxBus::new(c.clone(), mixer.clone(), vec![
+            AuxSend { channel: "snare".to_string(), level: ConstSignal::new(c.clone(), 0.3), point: SendPoint::PostFader },
+            AuxSend { channel: "synths".to_string(), level: ConstSignal::new(c.clone(), 0.3), point: SendPoint::PostFader }
+        ]),
+        ConstSignal::new(c.clone(), 0.7),
+        ConstSignal::new(c.clone(), 0.5),
+        ConstSignal::new(c.clone(), 0.02),
+        ConstSignal::new(c.clone(), 1.0),
+        // Fully wet, since it's on a send.
+        ConstSignal::new(c.clone(), 1.0)
     );
 
-    let mix = Mixer::new(c.clone(), vec![kick, hihat, hihat2, snare, bass, synths_mixed]);
-    let master = WaveShaperEffect::new(
+    let mix = Mixer::new(c.clone(), vec![mixer, reverb]);
+    let master = Limiter::new(
         c.clone(),
         Gain::new(
             c.clone(),
             mix.clone(),
             ConstSignal::new(c.clone(), decibels(-5.0))
         ),
-        HardClipper::new()
+        0.005,
+        ConstSignal::new(c.clone(), -0.3),
+        ConstSignal::new(c.clone(), 0.1)
     );
 
     println!("Rendering audio...");
-    let (left, right) = dawr::render_audio(c, master, TimeCalculator::new(160.0).add_bars(16.0).time() as usize);
+    let sample_rate = c.sample_rate();
+    let length = TimeCalculator::for_clock(160.0, &c).add_bars(16.0);
+    let (left, right) = dawr::render_audio(c, master, length.time() as usize);
 
     println!("Saving audio to Output.wav...");
-    dawr::files::save_stereo_to_wav(&left, &right, "Output.wav");
+    dawr::files::save_stereo_to_wav_at_rate(&left, &right, "Output.wav", sample_rate);
+
+    println!("Saving audio to Output.flac...");
+    let tags = files::FlacTags {
+        title: Some("DAWr Demo Track".to_string()),
+        bpm: Some(160.0),
+        ..Default::default()
+    };
+    files::save_stereo_to_flac(&left, &right, "Output.flac", &files::ExportSettings::new(sample_rate), &tags).unwrap();
+
+    // Playback just streams the rendered buffers, so it can use much smaller blocks than the
+    // render for lower latency.
+    let playback_chunk_size = 256;
 
     let pa = try!(pa::PortAudio::new());
     let mut settings = try!(pa.default_output_stream_settings(
             2, // num channels
-            consts::SAMPLE_RATE as f64,
-            consts::CHUNK_SIZE as u32
+            sample_rate as f64,
+            playback_chunk_size as u32
         ));
     settings.flags = pa::stream_flags::CLIP_OFF;
 
@@ -226,7 +237,7 @@ fn run() -> Result<(), pa::Error> {
     let mut position = 0;
 
     let callback = move |pa::OutputStreamCallbackArgs { buffer, frames, .. }| {
-        assert_eq!(frames, consts::CHUNK_SIZE);
+        assert_eq!(frames, playback_chunk_size);
         for f in 0..frames {
             if position < right.len() && position < left.len() {
                 let left_sample = left[position];
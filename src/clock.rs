@@ -4,19 +4,50 @@ use std::rc::Rc;
 use consts;
 
 pub struct Clock {
-    time: Cell<u64>
+    time: Cell<u64>,
+    sample_rate: u32,
+    // The length of the current chunk in samples.
+    chunk_size: Cell<usize>
 }
 
 impl Clock {
     pub fn new() -> Rc<Self> {
-        Rc::new(Self { time: Cell::new(0) })
+        Self::with_sample_rate(consts::SAMPLE_RATE)
+    }
+
+    // Every device reads the sample rate from its clock, so this sets it for the whole graph.
+    pub fn with_sample_rate(sample_rate: u32) -> Rc<Self> {
+        Self::with_settings(sample_rate, consts::CHUNK_SIZE)
+    }
+
+    // Smaller chunks mean lower latency for live playback, larger ones are faster for offline
+    // rendering.
+    pub fn with_settings(sample_rate: u32, chunk_size: usize) -> Rc<Self> {
+        assert!(chunk_size >= 1);
+        Rc::new(Self { time: Cell::new(0), sample_rate, chunk_size: Cell::new(chunk_size) })
     }
 
     pub fn time(&self) -> u64 {
         self.time.get()
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size.get()
+    }
+
     pub fn increment(&self) {
-        self.time.set(self.time.get() + (consts::CHUNK_SIZE as u64));
+        self.time.set(self.time.get() + (self.chunk_size.get() as u64));
+    }
+
+    // Moves on to the next chunk and makes it `next_chunk_size` samples long, e.g. to match the
+    // number of frames an audio callback asks for.
+    pub fn increment_with_chunk_size(&self, next_chunk_size: usize) {
+        assert!(next_chunk_size >= 1);
+        self.increment();
+        self.chunk_size.set(next_chunk_size);
     }
 }
@@ -1,5 +1,7 @@
 
+// The sample rate Clock::new() uses. Use Clock::with_sample_rate() for others.
 pub const SAMPLE_RATE : u32 = 44100;
+// The default chunk size. Use Clock::with_settings() for others.
 pub const CHUNK_SIZE : usize = 4096;
 /* This won't create a bug unless people are working with eons of audio. */
 pub const TIME_INFINITY : u64 = 0xFFFFFFFFFFFFFFFF;
@@ -1,20 +1,35 @@
 use consts;
+use clock::Clock;
 
 // Right now this only supports 4/4 time.
 pub struct TimeCalculator {
     sample_time: u64,
-    bpm: f64
+    bpm: f64,
+    sample_rate: u32
 }
 
 impl TimeCalculator {
+    // Assumes the default 44.1kHz. If the Clock runs at another rate, use for_clock so the event
+    // times line up with it.
     pub fn new(bpm: f64) -> Self {
-        Self { sample_time: 0, bpm }
+        Self::with_sample_rate(bpm, consts::SAMPLE_RATE)
+    }
+
+    // Times at the sample rate of the Clock they're for.
+    pub fn for_clock(bpm: f64, clock: &Clock) -> Self {
+        Self::with_sample_rate(bpm, clock.sample_rate())
+    }
+
+    // Use the same sample rate as the Clock the times are for.
+    pub fn with_sample_rate(bpm: f64, sample_rate: u32) -> Self {
+        Self { sample_time: 0, bpm, sample_rate }
     }
 
     pub fn add_seconds(&self, seconds: f64) -> Self {
         Self {
-            sample_time: self.sample_time + (consts::SAMPLE_RATE as f64 * seconds) as u64,
-            bpm: self.bpm
+            sample_time: self.sample_time + (self.sample_rate as f64 * seconds) as u64,
+            bpm: self.bpm,
+            sample_rate: self.sample_rate
         }
     }
 
@@ -38,8 +53,22 @@ impl TimeCalculator {
     pub fn time(&self) -> u64 {
         self.sample_time
     }
+
+    // The same time in seconds, e.g. for tempo-synced delay times.
+    pub fn seconds(&self) -> f64 {
+        self.sample_time as f64 / self.sample_rate as f64
+    }
+
+    // The frequency of something that repeats once every this long, e.g. for tempo-synced LFOs.
+    pub fn frequency(&self) -> f64 {
+        1.0 / self.seconds()
+    }
 }
 
 pub fn decibels(db: f32) -> f32 {
     10.0_f32.powf(db/20.0)
 }
+
+pub fn gain_to_decibels(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
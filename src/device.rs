@@ -12,10 +12,11 @@ pub struct MonoStateContainer<T> {
 
 impl<T> MonoStateContainer<T> {
     pub fn new(clock: Rc<Clock>, state: T) -> Self {
+        let chunk_size = clock.chunk_size();
         Self {
             clock: clock,
             output_time: Cell::new(consts::TIME_INFINITY),
-            output: RefCell::new(vec![0.0; consts::CHUNK_SIZE]),
+            output: RefCell::new(vec![0.0; chunk_size]),
             state: RefCell::new(state)
         }
     }
@@ -28,6 +29,14 @@ impl<T> MonoStateContainer<T> {
         self.clock.time()
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.clock.sample_rate()
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.clock.chunk_size()
+    }
+
     pub fn mark_as_up_to_date(&self) {
         self.output_time.set(self.clock.time());
     }
@@ -36,8 +45,15 @@ impl<T> MonoStateContainer<T> {
         self.output.borrow()
     }
 
+    // The buffer is resized to the current chunk size, which can change from chunk to chunk.
     pub fn borrow_to_modify(&self) -> RefMut<Vec<f32>> {
-        self.output.borrow_mut()
+        let mut output = self.output.borrow_mut();
+        output.resize(self.clock.chunk_size(), 0.0);
+        output
+    }
+
+    pub fn borrow_state(&self) -> Ref<T> {
+        self.state.borrow()
     }
 
     pub fn borrow_state_mut(&self) -> RefMut<T> {
@@ -60,11 +76,12 @@ pub struct StereoStateContainer<T> {
 
 impl<T> StereoStateContainer<T> {
     pub fn new(clock: Rc<Clock>, state: T) -> Self {
+        let chunk_size = clock.chunk_size();
         Self {
             clock: clock,
             output_time: Cell::new(consts::TIME_INFINITY),
-            left: RefCell::new(vec![0.0; consts::CHUNK_SIZE]),
-            right: RefCell::new(vec![0.0; consts::CHUNK_SIZE]),
+            left: RefCell::new(vec![0.0; chunk_size]),
+            right: RefCell::new(vec![0.0; chunk_size]),
             state: RefCell::new(state)
         }
     }
@@ -77,6 +94,14 @@ impl<T> StereoStateContainer<T> {
         self.clock.time()
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.clock.sample_rate()
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.clock.chunk_size()
+    }
+
     pub fn mark_as_up_to_date(&self) {
         self.output_time.set(self.clock.time());
     }
@@ -85,12 +110,17 @@ impl<T> StereoStateContainer<T> {
         (self.left.borrow(), self.right.borrow())
     }
 
+    // The buffers are resized to the current chunk size, which can change from chunk to chunk.
     pub fn borrow_left_to_modify(&self) -> RefMut<Vec<f32>> {
-        self.left.borrow_mut()
+        let mut left = self.left.borrow_mut();
+        left.resize(self.clock.chunk_size(), 0.0);
+        left
     }
 
     pub fn borrow_right_to_modify(&self) -> RefMut<Vec<f32>> {
-        self.right.borrow_mut()
+        let mut right = self.right.borrow_mut();
+        right.resize(self.clock.chunk_size(), 0.0);
+        right
     }
 
     pub fn borrow_state_mut(&self) -> RefMut<T> {
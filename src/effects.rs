@@ -1,10 +1,13 @@
 
 use device::*;
 use clock::*;
+use events::EventSource;
 use std::rc::Rc;
 use std::cell::*;
-use consts;
 use conversions;
+use files;
+use error::DawrError;
+use std::f32::consts::PI;
 
 
 pub struct Mixer {
@@ -26,21 +29,213 @@ impl StereoEmitter for Mixer {
             let mut left = self.device.borrow_left_to_modify();
             let mut right = self.device.borrow_right_to_modify();
 
-            for i in 0..consts::CHUNK_SIZE {
-                // TODO: just use the buffer instead of temp variables
-                let mut ls = 0.0;
-                for j in 0..self.inputs.len() {
-                    ls += self.inputs[j].output().0[i];
-                }
-                left[i] = ls;
+            fill(&mut left, 0.0);
+            fill(&mut right, 0.0);
+
+            for input in self.inputs.iter() {
+                let (input_left, input_right) = input.output();
+                add_into(&mut left, &input_left);
+                add_into(&mut right, &input_right);
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Chunk-wide helpers. These loop over whole slices so the compiler can vectorize them.
+
+fn fill(output: &mut [f32], value: f32) {
+    for sample in output.iter_mut() {
+        *sample = value;
+    }
+}
+
+fn add_into(output: &mut [f32], input: &[f32]) {
+    for (sample, x) in output.iter_mut().zip(input.iter()) {
+        *sample += *x;
+    }
+}
+
+fn multiply(output: &mut [f32], a: &[f32], b: &[f32]) {
+    for (sample, (x, y)) in output.iter_mut().zip(a.iter().zip(b.iter())) {
+        *sample = *x * *y;
+    }
+}
+
+// One input of a ChannelMixer, with its own gain and pan. The output is post-fader and ignores
+// mute and solo, which the ChannelMixer and AuxBus take care of.
+pub struct MixerChannel {
+    device: StereoStateContainer<()>,
+    name: String,
+    input: Rc<StereoEmitter>,
+    gain: Rc<MonoEmitter>,
+    // Values in [-1, 1].
+    pan: Rc<MonoEmitter>,
+    // These take effect from the next chunk.
+    muted: Cell<bool>,
+    soloed: Cell<bool>
+}
+
+impl MixerChannel {
+    pub fn new(clock: Rc<Clock>, name: &str, input: Rc<StereoEmitter>, gain: Rc<MonoEmitter>, pan: Rc<MonoEmitter>) -> Rc<Self> {
+        Rc::new(Self {
+            device: StereoStateContainer::<()>::new(clock, ()),
+            name: name.to_string(),
+            input,
+            gain,
+            pan,
+            muted: Cell::new(false),
+            soloed: Cell::new(false)
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.set(muted);
+    }
+
+    pub fn set_soloed(&self, soloed: bool) {
+        self.soloed.set(soloed);
+    }
+}
+
+impl StereoEmitter for MixerChannel {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+            let (input_left, input_right) = self.input.output();
+            let gain = self.gain.output();
+            let pan = self.pan.output();
+
+            for i in 0..self.device.chunk_size() {
+                let (left_gain, right_gain) = pan_gains(pan[i]);
+                left[i] = input_left[i] * gain[i] * left_gain;
+                right[i] = input_right[i] * gain[i] * right_gain;
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Sums MixerChannels. Muted channels are left out, and if any channel is soloed, only the soloed
+// channels are heard. Every channel is still pulled each chunk, so the devices feeding a silent
+// channel keep running and pick up where they should when it's unmuted.
+pub struct ChannelMixer {
+    device: StereoStateContainer<()>,
+    channels: Vec<Rc<MixerChannel>>
+}
+
+impl ChannelMixer {
+    pub fn new(clock: Rc<Clock>, channels: Vec<Rc<MixerChannel>>) -> Rc<Self> {
+        Rc::new(Self { device: StereoStateContainer::<()>::new(clock, ()), channels })
+    }
+
+    pub fn channel(&self, name: &str) -> Option<Rc<MixerChannel>> {
+        self.channels.iter().find(|channel| channel.name == name).cloned()
+    }
+
+    fn is_audible(&self, channel: &MixerChannel) -> bool {
+        let any_soloed = self.channels.iter().any(|c| c.soloed.get());
+        !channel.muted.get() && (!any_soloed || channel.soloed.get())
+    }
+}
+
+impl StereoEmitter for ChannelMixer {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+
+            fill(&mut left, 0.0);
+            fill(&mut right, 0.0);
 
-                let mut rs = 0.0;
-                for j in 0..self.inputs.len() {
-                    rs += self.inputs[j].output().1[i];
+            for channel in self.channels.iter() {
+                let (input_left, input_right) = channel.output();
+                if self.is_audible(channel) {
+                    add_into(&mut left, &input_left);
+                    add_into(&mut right, &input_right);
                 }
-                right[i] = rs;
             }
+        }
+        self.device.borrow_output()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SendPoint {
+    // The channel's input, before its gain and pan.
+    PreFader,
+    PostFader
+}
+
+pub struct AuxSend {
+    // The name of the ChannelMixer channel to send from.
+    pub channel: String,
+    pub level: Rc<MonoEmitter>,
+    pub point: SendPoint
+}
+
+// An auxiliary bus fed by sends from a ChannelMixer's channels, e.g. to share one Reverb between
+// several channels. Mix its output (usually through an effect) back in alongside the ChannelMixer.
+pub struct AuxBus {
+    device: StereoStateContainer<()>,
+    mixer: Rc<ChannelMixer>,
+    sends: Vec<(Rc<MixerChannel>, AuxSend)>
+}
+
+impl AuxBus {
+    pub fn new(clock: Rc<Clock>, mixer: Rc<ChannelMixer>, sends: Vec<AuxSend>) -> Rc<Self> {
+        Self::try_new(clock, mixer, sends).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(clock: Rc<Clock>, mixer: Rc<ChannelMixer>, sends: Vec<AuxSend>) -> Result<Rc<Self>, DawrError> {
+        let mut channel_sends = Vec::with_capacity(sends.len());
+        for send in sends {
+            let channel = match mixer.channel(&send.channel) {
+                Some(channel) => channel,
+                None => return Err(DawrError::InvalidArgument(format!("No mixer channel is named {}", send.channel)))
+            };
+            channel_sends.push((channel, send));
+        }
+        Ok(Rc::new(Self { device: StereoStateContainer::<()>::new(clock, ()), mixer, sends: channel_sends }))
+    }
+}
+
+impl StereoEmitter for AuxBus {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
 
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+
+            fill(&mut left, 0.0);
+            fill(&mut right, 0.0);
+
+            for &(ref channel, ref send) in self.sends.iter() {
+                // Pull the channel and level even when the send is silent, like the ChannelMixer does.
+                let (post_left, post_right) = channel.output();
+                let level = send.level.output();
+                if !self.mixer.is_audible(channel) {
+                    continue;
+                }
+                let (input_left, input_right) = match send.point {
+                    SendPoint::PreFader => channel.input.output(),
+                    SendPoint::PostFader => (post_left, post_right)
+                };
+                for i in 0..self.device.chunk_size() {
+                    left[i] += input_left[i] * level[i];
+                    right[i] += input_right[i] * level[i];
+                }
+            }
         }
         self.device.borrow_output()
     }
@@ -67,13 +262,10 @@ impl StereoEmitter for Gain {
             let mut right = self.device.borrow_right_to_modify();
 
             let boost_buf = self.boost.output();
-            let input_left = self.input.output().0;
-            let input_right = self.input.output().1;
+            let (input_left, input_right) = self.input.output();
 
-            for i in 0..consts::CHUNK_SIZE {
-                left[i] = boost_buf[i]*input_left[i];
-                right[i] = boost_buf[i]*input_right[i];
-            }
+            multiply(&mut left, &boost_buf, &input_left);
+            multiply(&mut right, &boost_buf, &input_right);
         }
         self.device.borrow_output()
     }
@@ -95,15 +287,27 @@ impl MonoEmitter for ConstSignal {
         if self.device.clock_advanced() {
             self.device.mark_as_up_to_date();
             let mut chunk = self.device.borrow_to_modify();
-
-            for i in 0..chunk.len() {
-                chunk[i] = self.value;
-            }
+            fill(&mut chunk, self.value);
         }
         self.device.borrow_output()
     }
 }
 
+// The (left, right) gains for a pan position in [-1, 1], with both at -3dB in the center.
+fn pan_gains(position: f32) -> (f32, f32) {
+    let minus3 = conversions::decibels(-3.0);
+
+    let pan_pow2 = position.powf(2.0);
+    let less = minus3 - pan_pow2 * minus3;
+    let more = minus3 + pan_pow2 * (1.0 - minus3);
+
+    if position > 0.0 {
+        (less, more)
+    } else {
+        (more, less)
+    }
+}
+
 pub struct Pan {
     device: StereoStateContainer<()>,
     input: Rc<StereoEmitter>,
@@ -123,26 +327,14 @@ impl StereoEmitter for Pan {
 
             let mut left = self.device.borrow_left_to_modify();
             let mut right = self.device.borrow_right_to_modify();
-            let input_left = self.input.output().0;
-            let input_right = self.input.output().1;
+            let (input_left, input_right) = self.input.output();
 
             let positions = self.position.output();
 
-            for i in 0..consts::CHUNK_SIZE {
-
-                let minus3 = conversions::decibels(-3.0);
-
-                let pan_pow2 = positions[i].powf(2.0);
-                let less = minus3 - pan_pow2 * minus3;
-                let more = minus3 + pan_pow2 * (1.0 - minus3);
-
-                if positions[i] > 0.0 {
-                    left[i] = input_left[i]*less;
-                    right[i] = input_right[i]*more;
-                } else {
-                    left[i] = input_left[i]*more;
-                    right[i] = input_right[i]*less;
-                }
+            for i in 0..self.device.chunk_size() {
+                let (left_gain, right_gain) = pan_gains(positions[i]);
+                left[i] = input_left[i]*left_gain;
+                right[i] = input_right[i]*right_gain;
             }
         }
         self.device.borrow_output()
@@ -172,10 +364,8 @@ impl StereoEmitter for MonoToStereo {
             let mut left = self.device.borrow_left_to_modify();
             let mut right = self.device.borrow_right_to_modify();
 
-            for i in 0..consts::CHUNK_SIZE {
-                left[i] = output[i];
-                right[i] = output[i];
-            }
+            left.copy_from_slice(&output);
+            right.copy_from_slice(&output);
         }
         self.device.borrow_output()
     }
@@ -201,10 +391,9 @@ impl MonoEmitter for StereoToMono {
             self.device.mark_as_up_to_date();
 
             let mut output = self.device.borrow_to_modify();
-            let left = self.input.output().0;
-            let right = self.input.output().1;
+            let (left, right) = self.input.output();
 
-            for i in 0..consts::CHUNK_SIZE {
+            for i in 0..self.device.chunk_size() {
                 output[i] = 0.5*(left[i] + right[i]);
             }
         }
@@ -239,18 +428,291 @@ impl WaveShaper for HardClipper {
     }
 }
 
+// Smooth saturation using tanh.
+pub struct SoftClipper {
+
+}
+
+impl SoftClipper {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self { })
+    }
+}
+
+impl WaveShaper for SoftClipper {
+    fn shape(&self, sample: f32) -> f32 {
+        sample.tanh()
+    }
+}
+
+// A gentler knee than HardClipper, reaching 1.0 with zero slope at an input of 1.0.
+pub struct CubicClipper {
+
+}
+
+impl CubicClipper {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self { })
+    }
+}
+
+impl WaveShaper for CubicClipper {
+    fn shape(&self, sample: f32) -> f32 {
+        if sample > 1.0 {
+            1.0
+        } else if sample < -1.0 {
+            -1.0
+        } else {
+            1.5 * sample - 0.5 * sample.powf(3.0)
+        }
+    }
+}
+
+// Reflects the signal back down whenever it goes past the threshold.
+pub struct Foldback {
+    threshold: f32
+}
+
+impl Foldback {
+    pub fn new(threshold: f32) -> Rc<Self> {
+        assert!(threshold > 0.0);
+        Rc::new(Self { threshold })
+    }
+}
+
+impl WaveShaper for Foldback {
+    fn shape(&self, sample: f32) -> f32 {
+        let t = self.threshold;
+        ((sample - t).rem_euclid(4.0 * t) - 2.0 * t).abs() - t
+    }
+}
+
+// Rounds the signal to the levels of a signed integer with `bits` bits.
+pub struct BitCrusher {
+    levels: f32
+}
+
+impl BitCrusher {
+    pub fn new(bits: u32) -> Rc<Self> {
+        assert!(bits >= 1);
+        Rc::new(Self { levels: 2.0_f32.powf((bits - 1) as f32) })
+    }
+}
+
+impl WaveShaper for BitCrusher {
+    fn shape(&self, sample: f32) -> f32 {
+        (sample * self.levels).round() / self.levels
+    }
+}
+
+// Asymmetric saturation like an overdriven tube stage. The bias shifts the tanh curve so the
+// positive and negative halves clip differently, adding even harmonics.
+pub struct TubeSaturator {
+    bias: f32
+}
+
+impl TubeSaturator {
+    pub fn new(bias: f32) -> Rc<Self> {
+        Rc::new(Self { bias })
+    }
+}
+
+impl WaveShaper for TubeSaturator {
+    fn shape(&self, sample: f32) -> f32 {
+        (sample + self.bias).tanh() - self.bias.tanh()
+    }
+}
+
+// A transfer curve given as (input, output) breakpoints with linear interpolation between them.
+// Inputs beyond the first or last breakpoint get that breakpoint's output.
+pub struct TransferCurve {
+    points: Vec<(f32, f32)>
+}
+
+impl TransferCurve {
+    pub fn from_breakpoints(points: Vec<(f32, f32)>) -> Rc<Self> {
+        Self::try_from_breakpoints(points).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // The breakpoints are (input, output) pairs and must be sorted by input.
+    pub fn try_from_breakpoints(points: Vec<(f32, f32)>) -> Result<Rc<Self>, DawrError> {
+        if points.is_empty() {
+            return Err(DawrError::InvalidArgument("A TransferCurve needs at least one breakpoint".to_string()));
+        }
+        if let Some(index) = (1..points.len()).find(|&i| !(points[i - 1].0 <= points[i].0)) {
+            return Err(DawrError::UnsortedEvents(index));
+        }
+        Ok(Rc::new(Self { points }))
+    }
+
+    // The table's samples are the outputs for inputs spaced evenly across [-1, 1].
+    pub fn from_table(table: &[f32]) -> Rc<Self> {
+        assert!(table.len() >= 2);
+        let step = 2.0 / (table.len() - 1) as f32;
+        Self::from_breakpoints(table.iter().enumerate().map(|(i, &output)| (-1.0 + i as f32 * step, output)).collect())
+    }
+
+    // Loads a table (see from_table) from a WAV file, mixing it down to mono.
+    pub fn from_wav(filename: &str) -> Rc<Self> {
+        Self::try_from_wav(filename).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_from_wav(filename: &str) -> Result<Rc<Self>, DawrError> {
+        let table = try!(files::try_load_wav_to_mono(filename));
+        if table.len() < 2 {
+            return Err(DawrError::InvalidArgument(format!("{} needs at least 2 samples to be a transfer curve", filename)));
+        }
+        Ok(Self::from_table(&table))
+    }
+}
+
+impl WaveShaper for TransferCurve {
+    fn shape(&self, sample: f32) -> f32 {
+        // NaN fails both range checks below, so pass it through before the search.
+        if sample.is_nan() {
+            return sample;
+        }
+        let first = self.points[0];
+        let last = self.points[self.points.len() - 1];
+        if sample <= first.0 {
+            return first.1;
+        }
+        if sample >= last.0 {
+            return last.1;
+        }
+
+        // The first breakpoint past the sample. The checks above mean it's never the first one.
+        let next = self.points.partition_point(|p| p.0 <= sample);
+        let (x0, y0) = self.points[next - 1];
+        let (x1, y1) = self.points[next];
+        y0 + (y1 - y0) * (sample - x0) / (x1 - x0)
+    }
+}
+
 pub struct WaveShaperEffect {
-    device: StereoStateContainer<()>,
+    device: StereoStateContainer<WaveShaperState>,
     input: Rc<StereoEmitter>,
-    shaper: Rc<WaveShaper>
+    shaper: Rc<WaveShaper>,
+    // Multiplies the input before it's shaped.
+    drive: Rc<MonoEmitter>,
+    // Values in [0, 1], 0 being completely dry, 1 being completely wet.
+    mix: Rc<MonoEmitter>
+}
+
+struct WaveShaperState {
+    left: Oversampler,
+    right: Oversampler
+}
+
+// Taps per phase of the oversampling filters. This is also the latency in samples when oversampling.
+const OVERSAMPLING_TAPS : usize = 16;
+
+// Runs a waveshaper at `factor` times the sample rate, with windowed-sinc filters for upsampling and
+// downsampling, so harmonics the shaper creates above the original Nyquist frequency are filtered
+// out instead of aliasing.
+struct Oversampler {
+    factor: usize,
+    kernel: Vec<f32>,
+    // Ring buffers of the last OVERSAMPLING_TAPS + 1 input samples and the last kernel.len()
+    // shaped samples at the oversampled rate. Each position is where the newest sample is, and
+    // older ones follow it, wrapping around.
+    input: Vec<f32>,
+    input_position: usize,
+    shaped: Vec<f32>,
+    shaped_position: usize
+}
+
+impl Oversampler {
+    fn new(factor: usize) -> Self {
+        assert!(factor >= 1);
+        let length = factor * OVERSAMPLING_TAPS + 1;
+        let center = (length - 1) as f32 / 2.0;
+        let cutoff = 0.5 / factor as f32;
+
+        let mut kernel = Vec::<f32>::new();
+        for k in 0..length {
+            let x = k as f32 - center;
+            let sinc = if x == 0.0 { 1.0 } else { (2.0 * PI * cutoff * x).sin() / (2.0 * PI * cutoff * x) };
+            // Blackman window.
+            let phase = 2.0 * PI * k as f32 / (length - 1) as f32;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            kernel.push(sinc * window);
+        }
+        let sum : f32 = kernel.iter().sum();
+        for tap in kernel.iter_mut() {
+            *tap /= sum;
+        }
+
+        Self {
+            factor,
+            kernel,
+            input: vec![0.0; OVERSAMPLING_TAPS + 1],
+            input_position: 0,
+            shaped: vec![0.0; length],
+            shaped_position: 0
+        }
+    }
+
+    // Returns the shaped sample and the matching dry sample, both delayed by the filters' latency.
+    fn process(&mut self, sample: f32, shaper: &WaveShaper, drive: f32) -> (f32, f32) {
+        if self.factor == 1 {
+            return (shaper.shape(sample * drive), sample);
+        }
+
+        let input_length = self.input.len();
+        self.input_position = (self.input_position + input_length - 1) % input_length;
+        self.input[self.input_position] = sample;
+
+        let mut output = 0.0;
+        for phase in 0..self.factor {
+            // Only every factor-th tap lands on a real sample; the zeros stuffed in between drop out.
+            let mut upsampled = 0.0;
+            let mut k = phase;
+            let mut j = self.input_position;
+            while k < self.kernel.len() {
+                upsampled += self.kernel[k] * self.input[j];
+                k += self.factor;
+                j = if j + 1 == input_length { 0 } else { j + 1 };
+            }
+            upsampled *= self.factor as f32;
+
+            let shaped_length = self.shaped.len();
+            self.shaped_position = (self.shaped_position + shaped_length - 1) % shaped_length;
+            self.shaped[self.shaped_position] = shaper.shape(upsampled * drive);
+
+            if phase == 0 {
+                let (older, newest) = self.shaped.split_at(self.shaped_position);
+                output = self.kernel.iter().zip(newest.iter().chain(older.iter())).map(|(h, s)| h * s).sum();
+            }
+        }
+
+        // The dry sample from OVERSAMPLING_TAPS samples ago, the oldest in the buffer.
+        (output, self.input[(self.input_position + OVERSAMPLING_TAPS) % input_length])
+    }
 }
 
 impl WaveShaperEffect {
     pub fn new(clock: Rc<Clock>, input: Rc<StereoEmitter>, shaper: Rc<WaveShaper>) -> Rc<Self> {
+        Self::with_drive(
+            clock.clone(),
+            input,
+            shaper,
+            ConstSignal::new(clock.clone(), 1.0),
+            ConstSignal::new(clock.clone(), 1.0),
+            1
+        )
+    }
+
+    // `oversampling` is the factor to oversample by, 1 to turn it off. Oversampling delays the
+    // output by OVERSAMPLING_TAPS samples.
+    pub fn with_drive(clock: Rc<Clock>, input: Rc<StereoEmitter>, shaper: Rc<WaveShaper>, drive: Rc<MonoEmitter>, mix: Rc<MonoEmitter>, oversampling: usize) -> Rc<Self> {
+        let state = WaveShaperState { left: Oversampler::new(oversampling), right: Oversampler::new(oversampling) };
         Rc::new(Self {
-            device: StereoStateContainer::<()>::new(clock, ()),
+            device: StereoStateContainer::new(clock, state),
             input,
-            shaper
+            shaper,
+            drive,
+            mix
         })
     }
 }
@@ -260,28 +722,735 @@ impl StereoEmitter for WaveShaperEffect {
         if self.device.clock_advanced() {
             self.device.mark_as_up_to_date();
 
-            let input_left = self.input.output().0;
-            let input_right = self.input.output().1;
+            let (input_left, input_right) = self.input.output();
+            let drive = self.drive.output();
+            let mix = self.mix.output();
             let mut left = self.device.borrow_left_to_modify();
             let mut right = self.device.borrow_right_to_modify();
+            let mut state = self.device.borrow_state_mut();
 
-            for i in 0..consts::CHUNK_SIZE {
-                left[i] = self.shaper.shape(input_left[i]);
-                right[i] = self.shaper.shape(input_right[i]);
+            for i in 0..self.device.chunk_size() {
+                let (wet_left, dry_left) = state.left.process(input_left[i], &*self.shaper, drive[i]);
+                let (wet_right, dry_right) = state.right.process(input_right[i], &*self.shaper, drive[i]);
+                left[i] = (1.0 - mix[i]) * dry_left + mix[i] * wet_left;
+                right[i] = (1.0 - mix[i]) * dry_right + mix[i] * wet_right;
             }
         }
         self.device.borrow_output()
     }
 }
 
-//
-//struct Delay {
-//
-//}
-//
-//struct Fader {
-//
-//}
-// struct SoftClipper {
-//
-// }
+#[derive(Clone, Copy)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+    Peak,
+    LowShelf,
+    HighShelf
+}
+
+// A resonant filter using the biquad designs from Robert Bristow-Johnson's "Audio EQ Cookbook".
+pub struct BiquadFilter {
+    device: StereoStateContainer<BiquadFilterState>,
+    input: Rc<StereoEmitter>,
+    filter_type: FilterType,
+    // In Hz.
+    cutoff: Rc<MonoEmitter>,
+    q: Rc<MonoEmitter>,
+    // In dB, only used by the Peak and shelf filters.
+    gain: Rc<MonoEmitter>
+}
+
+#[derive(Clone, Copy)]
+struct BiquadChannel {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32
+}
+
+struct BiquadFilterState {
+    left: BiquadChannel,
+    right: BiquadChannel,
+    // The coefficients and the (cutoff, q, gain) they were computed for, so they're only
+    // recomputed when a parameter changes.
+    parameters: Option<(f32, f32, f32)>,
+    coefficients: BiquadCoefficients
+}
+
+// Normalized so that a0 == 1.
+struct BiquadCoefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32
+}
+
+impl BiquadFilter {
+    pub fn new(clock: Rc<Clock>, input: Rc<StereoEmitter>, filter_type: FilterType, cutoff: Rc<MonoEmitter>, q: Rc<MonoEmitter>, gain: Rc<MonoEmitter>) -> Rc<Self> {
+        let channel = BiquadChannel { x1: 0.0, x2: 0.0, y1: 0.0, y2: 0.0 };
+        Rc::new(Self {
+            device: StereoStateContainer::new(clock, BiquadFilterState {
+                left: channel,
+                right: channel,
+                parameters: None,
+                coefficients: BiquadCoefficients { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 }
+            }),
+            input,
+            filter_type,
+            cutoff,
+            q,
+            gain
+        })
+    }
+
+    fn coefficients(&self, cutoff: f32, q: f32, gain: f32) -> BiquadCoefficients {
+        let sample_rate = self.device.sample_rate() as f32;
+        let nyquist = sample_rate / 2.0;
+        let cutoff = cutoff.max(1.0).min(nyquist * 0.99);
+        let q = q.max(0.01);
+
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let (sin, cos) = (w0.sin(), w0.cos());
+        let alpha = sin / (2.0 * q);
+        let a = 10.0_f32.powf(gain / 40.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match self.filter_type {
+            FilterType::LowPass => {
+                ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::HighPass => {
+                ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::BandPass => {
+                (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::Notch => {
+                (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+            },
+            FilterType::Peak => {
+                (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+            },
+            FilterType::LowShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - beta),
+                    (a + 1.0) + (a - 1.0) * cos + beta,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - beta
+                )
+            },
+            FilterType::HighShelf => {
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - beta),
+                    (a + 1.0) - (a - 1.0) * cos + beta,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - beta
+                )
+            }
+        };
+
+        BiquadCoefficients { b0: b0 / a0, b1: b1 / a0, b2: b2 / a0, a1: a1 / a0, a2: a2 / a0 }
+    }
+}
+
+impl BiquadChannel {
+    fn process(&mut self, c: &BiquadCoefficients, x: f32) -> f32 {
+        let y = c.b0 * x + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+impl StereoEmitter for BiquadFilter {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let (input_left, input_right) = self.input.output();
+            let cutoff = self.cutoff.output();
+            let q = self.q.output();
+            let gain = self.gain.output();
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+            let mut state = self.device.borrow_state_mut();
+
+            for i in 0..self.device.chunk_size() {
+                let parameters = (cutoff[i], q[i], gain[i]);
+                if state.parameters != Some(parameters) {
+                    state.coefficients = self.coefficients(cutoff[i], q[i], gain[i]);
+                    state.parameters = Some(parameters);
+                }
+                let state = &mut *state;
+                left[i] = state.left.process(&state.coefficients, input_left[i]);
+                right[i] = state.right.process(&state.coefficients, input_right[i]);
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DelayMode {
+    Stereo,
+    // The input is mixed to mono and the echoes alternate between the left and right channels.
+    PingPong
+}
+
+pub struct Delay {
+    device: StereoStateContainer<DelayState>,
+    input: Rc<StereoEmitter>,
+    mode: DelayMode,
+    // In seconds, fractional values are interpolated. Use TimeCalculator::seconds() for tempo-synced
+    // times, e.g. TimeCalculator::new(160.0).add_eighths(1.5).seconds() for a dotted eighth.
+    time: Rc<MonoEmitter>,
+    // How much of the delayed signal is fed back in. Keep it below 1.0 or the echoes will grow.
+    feedback: Rc<MonoEmitter>,
+    // Values in [0, 1], 0 being completely dry, 1 being completely wet.
+    mix: Rc<MonoEmitter>
+}
+
+struct DelayState {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    write_position: usize
+}
+
+impl Delay {
+    pub fn new(clock: Rc<Clock>, input: Rc<StereoEmitter>, mode: DelayMode, max_seconds: f64, time: Rc<MonoEmitter>, feedback: Rc<MonoEmitter>, mix: Rc<MonoEmitter>) -> Rc<Self> {
+        // One extra sample for interpolating at the longest delay.
+        let length = (max_seconds * clock.sample_rate() as f64).ceil() as usize + 2;
+        let state = DelayState { left: vec![0.0; length], right: vec![0.0; length], write_position: 0 };
+        Rc::new(Self {
+            device: StereoStateContainer::new(clock, state),
+            input,
+            mode,
+            time,
+            feedback,
+            mix
+        })
+    }
+}
+
+impl DelayState {
+    // Linearly interpolates the sample `delay` samples before the write position.
+    fn read(&self, buffer: &Vec<f32>, delay: f32) -> f32 {
+        let length = buffer.len();
+        let position = self.write_position as f32 + length as f32 - delay;
+        let index = position.floor() as usize;
+        let t = position - position.floor();
+        let a = buffer[index % length];
+        let b = buffer[(index + 1) % length];
+        a + (b - a) * t
+    }
+}
+
+impl StereoEmitter for Delay {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let (input_left, input_right) = self.input.output();
+            let time = self.time.output();
+            let feedback = self.feedback.output();
+            let mix = self.mix.output();
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+            let mut state = self.device.borrow_state_mut();
+
+            let max_delay = (state.left.len() - 2) as f32;
+            let sample_rate = self.device.sample_rate() as f32;
+
+            for i in 0..self.device.chunk_size() {
+                let delay = (time[i] * sample_rate).max(1.0).min(max_delay);
+                let delayed_left = state.read(&state.left, delay);
+                let delayed_right = state.read(&state.right, delay);
+
+                let position = state.write_position;
+                match self.mode {
+                    DelayMode::Stereo => {
+                        state.left[position] = input_left[i] + feedback[i] * delayed_left;
+                        state.right[position] = input_right[i] + feedback[i] * delayed_right;
+                    },
+                    DelayMode::PingPong => {
+                        state.left[position] = 0.5 * (input_left[i] + input_right[i]) + feedback[i] * delayed_right;
+                        state.right[position] = feedback[i] * delayed_left;
+                    }
+                }
+                state.write_position = (position + 1) % state.left.len();
+
+                left[i] = (1.0 - mix[i]) * input_left[i] + mix[i] * delayed_left;
+                right[i] = (1.0 - mix[i]) * input_right[i] + mix[i] * delayed_right;
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Freeverb tunings, in samples at 44.1kHz. The right channel's are offset by REVERB_STEREO_SPREAD
+// so the two channels decorrelate.
+const REVERB_COMB_TUNINGS : [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const REVERB_ALLPASS_TUNINGS : [usize; 4] = [556, 441, 341, 225];
+const REVERB_STEREO_SPREAD : usize = 23;
+const REVERB_MAX_PRE_DELAY_SECONDS : f32 = 1.0;
+
+struct CombFilter {
+    buffer: Vec<f32>,
+    position: usize,
+    // One-pole low-pass in the feedback path, which is what "damping" controls.
+    filter_store: f32
+}
+
+impl CombFilter {
+    fn new(length: usize) -> Self {
+        Self { buffer: vec![0.0; length], position: 0, filter_store: 0.0 }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.buffer[self.position] = input + self.filter_store * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct AllpassFilter {
+    buffer: Vec<f32>,
+    position: usize
+}
+
+impl AllpassFilter {
+    fn new(length: usize) -> Self {
+        Self { buffer: vec![0.0; length], position: 0 }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.position];
+        self.buffer[self.position] = input + buffered * 0.5;
+        self.position = (self.position + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+struct ReverbChannel {
+    combs: Vec<CombFilter>,
+    allpasses: Vec<AllpassFilter>
+}
+
+impl ReverbChannel {
+    fn new(spread: usize, sample_rate: u32) -> Self {
+        let scale = |tuning: usize| (tuning + spread) * sample_rate as usize / 44100;
+        Self {
+            combs: REVERB_COMB_TUNINGS.iter().map(|&t| CombFilter::new(scale(t))).collect(),
+            allpasses: REVERB_ALLPASS_TUNINGS.iter().map(|&t| AllpassFilter::new(scale(t))).collect()
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let mut output = 0.0;
+        for comb in self.combs.iter_mut() {
+            output += comb.process(input, feedback, damping);
+        }
+        for allpass in self.allpasses.iter_mut() {
+            output = allpass.process(output);
+        }
+        output
+    }
+}
+
+// A Freeverb-style reverb: eight parallel damped comb filters followed by four allpass filters per
+// channel.
+pub struct Reverb {
+    device: StereoStateContainer<ReverbState>,
+    input: Rc<StereoEmitter>,
+    // Values in [0, 1].
+    room_size: Rc<MonoEmitter>,
+    // Values in [0, 1], higher values make the tail darker.
+    damping: Rc<MonoEmitter>,
+    // In seconds, up to REVERB_MAX_PRE_DELAY_SECONDS.
+    pre_delay: Rc<MonoEmitter>,
+    // Values in [0, 1], 0 being mono, 1 being full stereo.
+    width: Rc<MonoEmitter>,
+    // Values in [0, 1], 0 being completely dry, 1 being completely wet.
+    mix: Rc<MonoEmitter>
+}
+
+struct ReverbState {
+    pre_delay_buffer: Vec<f32>,
+    pre_delay_position: usize,
+    left: ReverbChannel,
+    right: ReverbChannel
+}
+
+impl Reverb {
+    pub fn new(clock: Rc<Clock>, input: Rc<StereoEmitter>, room_size: Rc<MonoEmitter>, damping: Rc<MonoEmitter>, pre_delay: Rc<MonoEmitter>, width: Rc<MonoEmitter>, mix: Rc<MonoEmitter>) -> Rc<Self> {
+        let sample_rate = clock.sample_rate();
+        let pre_delay_length = (REVERB_MAX_PRE_DELAY_SECONDS * sample_rate as f32) as usize + 1;
+        let state = ReverbState {
+            pre_delay_buffer: vec![0.0; pre_delay_length],
+            pre_delay_position: 0,
+            left: ReverbChannel::new(0, sample_rate),
+            right: ReverbChannel::new(REVERB_STEREO_SPREAD, sample_rate)
+        };
+        Rc::new(Self {
+            device: StereoStateContainer::new(clock, state),
+            input,
+            room_size,
+            damping,
+            pre_delay,
+            width,
+            mix
+        })
+    }
+}
+
+impl StereoEmitter for Reverb {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let (input_left, input_right) = self.input.output();
+            let room_size = self.room_size.output();
+            let damping = self.damping.output();
+            let pre_delay = self.pre_delay.output();
+            let width = self.width.output();
+            let mix = self.mix.output();
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+            let mut state = self.device.borrow_state_mut();
+            let state = &mut *state;
+
+            let length = state.pre_delay_buffer.len();
+            let sample_rate = self.device.sample_rate() as f32;
+
+            for i in 0..self.device.chunk_size() {
+                // Freeverb's scaling of the parameters.
+                let feedback = room_size[i] * 0.28 + 0.7;
+                let damp = damping[i] * 0.4;
+
+                // Both channels share one mono pre-delay line, like Freeverb's mono input. 0.015 is
+                // Freeverb's fixed input gain.
+                state.pre_delay_buffer[state.pre_delay_position] = (input_left[i] + input_right[i]) * 0.015;
+                let delay = ((pre_delay[i] * sample_rate).max(0.0) as usize).min(length - 1);
+                let delayed = state.pre_delay_buffer[(state.pre_delay_position + length - delay) % length];
+                state.pre_delay_position = (state.pre_delay_position + 1) % length;
+
+                let wet_left = state.left.process(delayed, feedback, damp);
+                let wet_right = state.right.process(delayed, feedback, damp);
+
+                let wet = 3.0 * mix[i];
+                let wet1 = wet * (width[i] / 2.0 + 0.5);
+                let wet2 = wet * ((1.0 - width[i]) / 2.0);
+                let dry = 1.0 - mix[i];
+
+                left[i] = wet_left * wet1 + wet_right * wet2 + input_left[i] * dry;
+                right[i] = wet_right * wet1 + wet_left * wet2 + input_right[i] * dry;
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+pub struct CompressorSettings {
+    // In dB.
+    pub threshold: Rc<MonoEmitter>,
+    // E.g. 4.0 for 4:1.
+    pub ratio: Rc<MonoEmitter>,
+    // Width of the soft knee in dB, 0.0 for a hard knee.
+    pub knee: Rc<MonoEmitter>,
+    // In seconds.
+    pub attack: Rc<MonoEmitter>,
+    pub release: Rc<MonoEmitter>,
+    // In dB.
+    pub makeup: Rc<MonoEmitter>
+}
+
+pub struct Compressor {
+    device: StereoStateContainer<CompressorState>,
+    input: Rc<StereoEmitter>,
+    // The signal whose level drives the gain reduction, e.g. a kick drum for ducking. If it's None,
+    // the input is used.
+    sidechain: Option<Rc<StereoEmitter>>,
+    settings: CompressorSettings
+}
+
+struct CompressorState {
+    // Smoothed gain reduction in dB, always <= 0.
+    reduction: f32
+}
+
+// The silence floor for level detection, so log10 never sees zero.
+const DETECTOR_FLOOR_DB : f32 = -120.0;
+
+impl Compressor {
+    pub fn new(clock: Rc<Clock>, input: Rc<StereoEmitter>, sidechain: Option<Rc<StereoEmitter>>, settings: CompressorSettings) -> Rc<Self> {
+        Rc::new(Self {
+            device: StereoStateContainer::new(clock, CompressorState { reduction: 0.0 }),
+            input,
+            sidechain,
+            settings
+        })
+    }
+
+    // The static gain reduction in dB for a detector level in dB, with a quadratic soft knee.
+    fn gain_reduction(level: f32, threshold: f32, ratio: f32, knee: f32) -> f32 {
+        let over = level - threshold;
+        let slope = 1.0 / ratio.max(1.0) - 1.0;
+        if knee > 0.0 && 2.0 * over.abs() <= knee {
+            slope * (over + knee / 2.0).powf(2.0) / (2.0 * knee)
+        } else if over > 0.0 {
+            slope * over
+        } else {
+            0.0
+        }
+    }
+}
+
+// One-pole smoothing coefficient for a time constant in seconds.
+fn time_coefficient(seconds: f32, sample_rate: u32) -> f32 {
+    if seconds > 0.0 {
+        (-1.0 / (seconds * sample_rate as f32)).exp()
+    } else {
+        0.0
+    }
+}
+
+impl StereoEmitter for Compressor {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let (input_left, input_right) = self.input.output();
+            let key = match self.sidechain {
+                Some(ref sidechain) => sidechain.output(),
+                None => self.input.output()
+            };
+            let threshold = self.settings.threshold.output();
+            let ratio = self.settings.ratio.output();
+            let knee = self.settings.knee.output();
+            let attack = self.settings.attack.output();
+            let release = self.settings.release.output();
+            let makeup = self.settings.makeup.output();
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+            let mut state = self.device.borrow_state_mut();
+
+            for i in 0..self.device.chunk_size() {
+                let peak = key.0[i].abs().max(key.1[i].abs());
+                let level = conversions::gain_to_decibels(peak).max(DETECTOR_FLOOR_DB);
+                let target = Self::gain_reduction(level, threshold[i], ratio[i], knee[i]);
+
+                let coefficient = if target < state.reduction {
+                    time_coefficient(attack[i], self.device.sample_rate())
+                } else {
+                    time_coefficient(release[i], self.device.sample_rate())
+                };
+                state.reduction = target + (state.reduction - target) * coefficient;
+
+                let gain = conversions::decibels(state.reduction + makeup[i]);
+                left[i] = input_left[i] * gain;
+                right[i] = input_right[i] * gain;
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// A brickwall limiter. The input is delayed by the lookahead time so the gain can ramp down before
+// a peak arrives instead of clipping it.
+pub struct Limiter {
+    device: StereoStateContainer<LimiterState>,
+    input: Rc<StereoEmitter>,
+    // The highest output level in dB.
+    ceiling: Rc<MonoEmitter>,
+    // In seconds.
+    release: Rc<MonoEmitter>
+}
+
+struct LimiterState {
+    // Ring buffers, each lookahead samples long.
+    delayed_left: Vec<f32>,
+    delayed_right: Vec<f32>,
+    // The gain each recent sample needs to stay under the ceiling.
+    required: Vec<f32>,
+    // The held and released gain, which is averaged over the lookahead to smooth it.
+    released: Vec<f32>,
+    released_sum: f64,
+    gain: f32,
+    position: usize
+}
+
+impl Limiter {
+    pub fn new(clock: Rc<Clock>, input: Rc<StereoEmitter>, lookahead_seconds: f64, ceiling: Rc<MonoEmitter>, release: Rc<MonoEmitter>) -> Rc<Self> {
+        let length = ((lookahead_seconds * clock.sample_rate() as f64).round() as usize).max(1);
+        let state = LimiterState {
+            delayed_left: vec![0.0; length],
+            delayed_right: vec![0.0; length],
+            required: vec![1.0; length],
+            released: vec![1.0; length],
+            released_sum: length as f64,
+            gain: 1.0,
+            position: 0
+        };
+        Rc::new(Self {
+            device: StereoStateContainer::new(clock, state),
+            input,
+            ceiling,
+            release
+        })
+    }
+}
+
+impl StereoEmitter for Limiter {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let (input_left, input_right) = self.input.output();
+            let ceiling = self.ceiling.output();
+            let release = self.release.output();
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+            let mut state = self.device.borrow_state_mut();
+
+            let length = state.required.len();
+
+            for i in 0..self.device.chunk_size() {
+                let position = state.position;
+                let peak = input_left[i].abs().max(input_right[i].abs());
+                let limit = conversions::decibels(ceiling[i]);
+                state.required[position] = if peak > limit { limit / peak } else { 1.0 };
+
+                // Hold the lowest gain needed anywhere in the lookahead window, then release.
+                let held = state.required.iter().fold(1.0_f32, |a, &b| a.min(b));
+                state.gain = if held < state.gain {
+                    held
+                } else {
+                    held + (state.gain - held) * time_coefficient(release[i], self.device.sample_rate())
+                };
+
+                // Every sample in the window is at most the gain the oldest sample needs, so the
+                // average is too.
+                state.released_sum += state.gain as f64 - state.released[position] as f64;
+                state.released[position] = state.gain;
+                let gain = (state.released_sum / length as f64) as f32;
+
+                // The oldest sample in the window is the one that's due out.
+                state.delayed_left[position] = input_left[i];
+                state.delayed_right[position] = input_right[i];
+                let next = (position + 1) % length;
+                left[i] = state.delayed_left[next] * gain;
+                right[i] = state.delayed_right[next] * gain;
+                state.position = next;
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum AutomationCurve {
+    // Holds the previous value and jumps at the breakpoint.
+    Step,
+    Linear,
+    // Ramps by a constant ratio per sample, which sounds even for gains and frequencies. Falls back
+    // to Linear if the values aren't both non-zero with the same sign.
+    Exponential,
+    // Eases in and out of the ramp.
+    SCurve
+}
+
+// The value to reach at the event's time, and the shape of the ramp from the previous breakpoint.
+pub struct AutomationPoint {
+    pub value: f32,
+    pub curve: AutomationCurve
+}
+
+// An automation lane. Before the first breakpoint the output is the first breakpoint's value, and
+// after the last one it stays at the last one's value.
+pub struct Automation {
+    device: MonoStateContainer<AutomationState>,
+    points: Rc<EventSource<AutomationPoint>>
+}
+
+struct AutomationState {
+    // Index of the first breakpoint after the current time.
+    next: usize
+}
+
+impl Automation {
+    pub fn new(clock: Rc<Clock>, points: Rc<EventSource<AutomationPoint>>) -> Rc<Self> {
+        Self::try_new(clock, points).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // The points are already sorted by time, since EventSource checks that.
+    pub fn try_new(clock: Rc<Clock>, points: Rc<EventSource<AutomationPoint>>) -> Result<Rc<Self>, DawrError> {
+        if points.all_events().is_empty() {
+            return Err(DawrError::InvalidArgument("An Automation needs at least one point".to_string()));
+        }
+        Ok(Rc::new(Self { device: MonoStateContainer::new(clock, AutomationState { next: 0 }), points }))
+    }
+}
+
+impl AutomationCurve {
+    fn interpolate(&self, from: f32, to: f32, progress: f32) -> f32 {
+        match *self {
+            AutomationCurve::Step => from,
+            AutomationCurve::Linear => from + (to - from) * progress,
+            AutomationCurve::Exponential => {
+                if from * to > 0.0 {
+                    from * (to / from).powf(progress)
+                } else {
+                    from + (to - from) * progress
+                }
+            },
+            AutomationCurve::SCurve => {
+                let eased = progress * progress * (3.0 - 2.0 * progress);
+                from + (to - from) * eased
+            }
+        }
+    }
+}
+
+impl MonoEmitter for Automation {
+    fn output(&self) -> Ref<Vec<f32>> {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let points = self.points.all_events();
+            let mut chunk = self.device.borrow_to_modify();
+            let mut state = self.device.borrow_state_mut();
+
+            for i in 0..chunk.len() {
+                let time = self.device.time() + i as u64;
+                while state.next < points.len() && points[state.next].0 <= time {
+                    state.next += 1;
+                }
+
+                chunk[i] = if state.next == 0 {
+                    points[0].1.value
+                } else if state.next == points.len() {
+                    points[points.len() - 1].1.value
+                } else {
+                    let (start, ref from) = points[state.next - 1];
+                    let (end, ref to) = points[state.next];
+                    let progress = (time - start) as f32 / (end - start) as f32;
+                    to.curve.interpolate(from.value, to.value, progress)
+                };
+            }
+        }
+        self.device.borrow_output()
+    }
+}
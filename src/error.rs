@@ -0,0 +1,91 @@
+use hound;
+#[cfg(feature = "flac")]
+use claxon;
+#[cfg(feature = "vorbis")]
+use lewton;
+#[cfg(feature = "mp3")]
+use minimp3;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Errors the `try_` variants of constructors and the files functions return instead of panicking.
+#[derive(Debug)]
+pub enum DawrError {
+    // Reading or writing an audio file failed, e.g. because it doesn't exist or isn't a WAV file.
+    File(hound::Error),
+    // Opening a compressed audio file failed.
+    Io(io::Error),
+    // A compressed audio file couldn't be decoded.
+    Decode(String),
+    // The file isn't in a format we can read, or its decoder's cargo feature isn't enabled.
+    UnsupportedFormat(String),
+    // The events given to an EventSource weren't sorted by time, or a TransferCurve's breakpoints
+    // weren't sorted by input. Holds the index of the first one that's earlier than the one before
+    // it.
+    UnsortedEvents(usize),
+    // An argument was out of range or didn't match another one.
+    InvalidArgument(String)
+}
+
+impl fmt::Display for DawrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DawrError::File(ref error) => write!(f, "Audio file error: {}", error),
+            DawrError::Io(ref error) => write!(f, "Audio file error: {}", error),
+            DawrError::Decode(ref message) => write!(f, "Couldn't decode audio file: {}", message),
+            DawrError::UnsupportedFormat(ref filename) => write!(f, "Unsupported audio file format: {}", filename),
+            DawrError::UnsortedEvents(index) => write!(f, "Events are not sorted! Event {} is earlier than the one before it.", index),
+            DawrError::InvalidArgument(ref message) => write!(f, "Invalid argument: {}", message)
+        }
+    }
+}
+
+impl Error for DawrError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            DawrError::File(ref error) => Some(error),
+            DawrError::Io(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<hound::Error> for DawrError {
+    fn from(error: hound::Error) -> Self {
+        DawrError::File(error)
+    }
+}
+
+impl From<io::Error> for DawrError {
+    fn from(error: io::Error) -> Self {
+        DawrError::Io(error)
+    }
+}
+
+#[cfg(feature = "flac")]
+impl From<claxon::Error> for DawrError {
+    fn from(error: claxon::Error) -> Self {
+        match error {
+            claxon::Error::IoError(error) => DawrError::Io(error),
+            error => DawrError::Decode(error.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "vorbis")]
+impl From<lewton::VorbisError> for DawrError {
+    fn from(error: lewton::VorbisError) -> Self {
+        DawrError::Decode(error.to_string())
+    }
+}
+
+#[cfg(feature = "mp3")]
+impl From<minimp3::Error> for DawrError {
+    fn from(error: minimp3::Error) -> Self {
+        match error {
+            minimp3::Error::Io(error) => DawrError::Io(error),
+            error => DawrError::Decode(error.to_string())
+        }
+    }
+}
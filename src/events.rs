@@ -2,6 +2,7 @@ use std::cell::*;
 use std::rc::Rc;
 use clock::Clock;
 use consts;
+use error::DawrError;
 
 pub struct EventSource<T> {
     clock: Rc<Clock>,
@@ -12,32 +13,27 @@ pub struct EventSource<T> {
 
 impl<T> EventSource<T> {
     pub fn new(events: Vec<(u64, T)>, clock: Rc<Clock>) -> Rc<Self> {
-        if !Self::is_sorted(&events) {
-            panic!("Events are not sorted by time!");
+        Self::try_new(events, clock).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(events: Vec<(u64, T)>, clock: Rc<Clock>) -> Result<Rc<Self>, DawrError> {
+        if let Some(index) = Self::first_unsorted(&events) {
+            return Err(DawrError::UnsortedEvents(index));
         }
-        Rc::new(Self {
+        Ok(Rc::new(Self {
             clock: clock,
             events: events,
             cursor: Cell::new(0),
             last_time: Cell::new(consts::TIME_INFINITY)
-        })
+        }))
     }
 
-    fn is_sorted(events: &Vec<(u64, T)>) -> bool {
-        if events.len() == 0 {
-            return true;
-        } else {
-            let mut max = events[0].0;
-            for i in 1..events.len() {
-                if events[i].0 < max {
-                    //println!("{} {}", events[i].0, max);
-                    return false;
-                } else {
-                    max = events[i].0;
-                }
-            }
-            return true;
-        }
+    fn first_unsorted(events: &Vec<(u64, T)>) -> Option<usize> {
+        (1..events.len()).find(|&i| events[i].0 < events[i - 1].0)
+    }
+
+    pub fn all_events(&self) -> &[(u64, T)] {
+        &self.events
     }
 
     pub fn events_this_chunk<'b>(&'b self) -> &'b[(u64, T)] {
@@ -52,7 +48,7 @@ impl<T> EventSource<T> {
         }
         let start = self.cursor.get();
         let mut end = start;
-        while end < self.events.len() && self.events[end].0 < time + (consts::CHUNK_SIZE as u64) {
+        while end < self.events.len() && self.events[end].0 < time + (self.clock.chunk_size() as u64) {
             end += 1;
         }
         &self.events[start..end]
@@ -0,0 +1,121 @@
+// A discrete Fourier transform of any length, for building the wavetable mipmaps. WAVE_SAMPLES
+// isn't a power of two, so this uses Bluestein's algorithm, which turns a DFT of length n into a
+// convolution that a power-of-two FFT can do in O(n log n).
+
+use std::f64::consts::PI;
+
+// Complex numbers as (real, imaginary).
+type Complex = (f64, f64);
+
+fn multiply(a: Complex, b: Complex) -> Complex {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn conjugate(a: Complex) -> Complex {
+    (a.0, -a.1)
+}
+
+pub struct Dft {
+    n: usize,
+    // e^(-i pi j^2 / n) for j in 0..n.
+    chirp: Vec<Complex>,
+    // The FFT of the conjugate chirp, wrapped around so negative indices are at the end.
+    chirp_spectrum: Vec<Complex>,
+    fft: Fft
+}
+
+impl Dft {
+    pub fn new(n: usize) -> Self {
+        assert!(n >= 1);
+        // j^2 is taken modulo 2n so the angles stay small and precise.
+        let chirp: Vec<Complex> = (0..n).map(|j| {
+            let angle = -PI * ((j * j) % (2 * n)) as f64 / n as f64;
+            (angle.cos(), angle.sin())
+        }).collect();
+
+        let fft = Fft::new((2 * n - 1).next_power_of_two());
+        let mut filter = vec![(0.0, 0.0); fft.n];
+        filter[0] = conjugate(chirp[0]);
+        for j in 1..n {
+            filter[j] = conjugate(chirp[j]);
+            filter[fft.n - j] = conjugate(chirp[j]);
+        }
+        fft.transform(&mut filter, false);
+
+        Self { n, chirp, chirp_spectrum: filter, fft }
+    }
+
+    // X[k] = sum over j of x[j] e^(-2 pi i j k / n).
+    pub fn forward(&self, input: &[Complex]) -> Vec<Complex> {
+        assert!(input.len() == self.n);
+        let mut buffer = vec![(0.0, 0.0); self.fft.n];
+        for j in 0..self.n {
+            buffer[j] = multiply(input[j], self.chirp[j]);
+        }
+        self.fft.transform(&mut buffer, false);
+        for (value, filter) in buffer.iter_mut().zip(self.chirp_spectrum.iter()) {
+            *value = multiply(*value, *filter);
+        }
+        self.fft.transform(&mut buffer, true);
+
+        let scale = 1.0 / self.fft.n as f64;
+        (0..self.n).map(|k| {
+            let value = multiply(buffer[k], self.chirp[k]);
+            (value.0 * scale, value.1 * scale)
+        }).collect()
+    }
+
+    // x[j] = sum over k of X[k] e^(2 pi i j k / n), without the 1/n.
+    pub fn inverse(&self, input: &[Complex]) -> Vec<Complex> {
+        let conjugated: Vec<Complex> = input.iter().map(|&value| conjugate(value)).collect();
+        self.forward(&conjugated).into_iter().map(conjugate).collect()
+    }
+}
+
+// An in-place radix-2 FFT, unscaled in both directions.
+struct Fft {
+    n: usize,
+    // e^(-2 pi i k / n) for k in 0..n/2.
+    twiddles: Vec<Complex>
+}
+
+impl Fft {
+    fn new(n: usize) -> Self {
+        assert!(n.is_power_of_two());
+        let twiddles = (0..n / 2).map(|k| {
+            let angle = -2.0 * PI * k as f64 / n as f64;
+            (angle.cos(), angle.sin())
+        }).collect();
+        Self { n, twiddles }
+    }
+
+    fn transform(&self, data: &mut [Complex], inverse: bool) {
+        let n = self.n;
+        let bits = n.trailing_zeros();
+        if bits == 0 {
+            return;
+        }
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::max_value().count_ones() - bits);
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        let mut size = 2;
+        while size <= n {
+            let stride = n / size;
+            for start in (0..n).step_by(size) {
+                for k in 0..size / 2 {
+                    let twiddle = self.twiddles[k * stride];
+                    let twiddle = if inverse { conjugate(twiddle) } else { twiddle };
+                    let even = data[start + k];
+                    let odd = multiply(data[start + k + size / 2], twiddle);
+                    data[start + k] = (even.0 + odd.0, even.1 + odd.1);
+                    data[start + k + size / 2] = (even.0 - odd.0, even.1 - odd.1);
+                }
+            }
+            size *= 2;
+        }
+    }
+}
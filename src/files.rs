@@ -1,49 +1,462 @@
 use hound;
-use std::i32;
-use std::i16;
+#[cfg(feature = "flac")]
+use claxon;
+#[cfg(feature = "vorbis")]
+use lewton;
+#[cfg(feature = "mp3")]
+use minimp3;
+use consts;
+use rand;
+use conversions::gain_to_decibels;
+use error::DawrError;
+use flac;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::fs::File;
+use std::io::Write;
 
-pub fn load_wav_to_stereo(filename: &str) -> (Vec<f32>, Vec<f32>) {
-    let mut reader = hound::WavReader::open(filename).unwrap();
-    let spec = reader.spec();
-    if spec.channels != 2 || spec.sample_rate != 44100 {
-        panic!("Sorry, this only supports 2-channel 44.1kHz WAV.");
+// How hard `resample` works. Better qualities use longer filters, which keep more of the top
+// octave and let less aliasing through, but take longer.
+#[derive(Clone, Copy)]
+pub enum ResampleQuality {
+    Fast,
+    Good,
+    Best
+}
+
+impl ResampleQuality {
+    // Taps on each side of the filter, the Kaiser window's beta and the cutoff as a fraction of
+    // the Nyquist frequency.
+    fn parameters(self) -> (usize, f64, f64) {
+        match self {
+            ResampleQuality::Fast => (8, 5.0, 0.85),
+            ResampleQuality::Good => (32, 8.0, 0.92),
+            ResampleQuality::Best => (128, 10.0, 0.97)
+        }
+    }
+}
+
+// The zeroth-order modified Bessel function of the first kind, for the Kaiser window.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+// Converts `samples` from `from_rate` to `to_rate` with a Kaiser-windowed sinc filter.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Vec<f32> {
+    assert!(from_rate > 0 && to_rate > 0);
+    if from_rate == to_rate {
+        return samples.to_vec();
+    }
+
+    let (half_taps, beta, rolloff) = quality.parameters();
+    let ratio = to_rate as f64 / from_rate as f64;
+    // When downsampling, the cutoff has to be below the new Nyquist frequency, and the filter gets
+    // proportionally longer to keep the same steepness.
+    let cutoff = rolloff * ratio.min(1.0);
+    let radius = half_taps as f64 / ratio.min(1.0);
+
+    // One side of the filter, tabulated over [0, radius] input samples and linearly interpolated.
+    let resolution = 256;
+    let kernel : Vec<f64> = (0..half_taps * resolution + 2).map(|j| {
+        let u = j as f64 / (half_taps * resolution) as f64;
+        if u >= 1.0 {
+            return 0.0;
+        }
+        let x = u * radius;
+        let sinc = if x == 0.0 { 1.0 } else { (PI * cutoff * x).sin() / (PI * cutoff * x) };
+        cutoff * sinc * bessel_i0(beta * (1.0 - u * u).sqrt()) / bessel_i0(beta)
+    }).collect();
+    let table_scale = (half_taps * resolution) as f64 / radius;
+
+    let length = (samples.len() as f64 * ratio).ceil() as usize;
+    let mut output = Vec::<f32>::with_capacity(length);
+    for n in 0..length {
+        // The output sample's position in input samples.
+        let t = n as f64 * from_rate as f64 / to_rate as f64;
+        let first = (t - radius).ceil().max(0.0) as usize;
+        let last = ((t + radius).floor() as usize).min(samples.len().saturating_sub(1));
+
+        let mut sum = 0.0;
+        for k in first..(last + 1) {
+            let position = (t - k as f64).abs() * table_scale;
+            let index = position as usize;
+            let fraction = position - index as f64;
+            let weight = kernel[index] + (kernel[index + 1] - kernel[index]) * fraction;
+            sum += samples[k] as f64 * weight;
+        }
+        output.push(sum as f32);
     }
+    output
+}
 
-    let samples = reader.samples::<i32>();
+// The audio in a file, one Vec per channel, at the file's sample rate.
+pub struct AudioData {
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: u32
+}
 
-    let mut left = Vec::<f32>::new();
-    let mut right = Vec::<f32>::new();
+impl AudioData {
+    // Mono files are copied to both sides. Files with more than two channels keep the first two,
+    // which WAV orders front left, front right.
+    pub fn to_stereo(mut self) -> (Vec<f32>, Vec<f32>) {
+        assert!(self.channels.len() >= 1);
+        if self.channels.len() == 1 {
+            let mono = self.channels.remove(0);
+            return (mono.clone(), mono);
+        }
+        self.channels.truncate(2);
+        let right = self.channels.pop().unwrap();
+        let left = self.channels.pop().unwrap();
+        (left, right)
+    }
 
-    let mut channel = 0;
+    // Averages all of the channels.
+    pub fn to_mono(self) -> Vec<f32> {
+        assert!(self.channels.len() >= 1);
+        let count = self.channels.len() as f32;
+        (0..self.channels[0].len()).map(|i| {
+            self.channels.iter().map(|channel| channel[i]).sum::<f32>() / count
+        }).collect()
+    }
 
-    for sample in samples {
-        if channel % 2 == 0 {
-            left.push((sample.unwrap() as f32) /  2.0_f32.powf(spec.bits_per_sample as f32));
-        } else {
-            right.push((sample.unwrap() as f32) / 2.0_f32.powf(spec.bits_per_sample as f32));
+    pub fn resampled(self, sample_rate: u32, quality: ResampleQuality) -> AudioData {
+        if self.sample_rate == sample_rate {
+            return self;
+        }
+        AudioData {
+            channels: self.channels.iter().map(|channel| resample(channel, self.sample_rate, sample_rate, quality)).collect(),
+            sample_rate
         }
-        channel = (channel + 1) % 2;
     }
+}
+
+// Reads integer WAVs of any bit depth, scaled so full scale is [-1, 1), and IEEE float WAVs as-is.
+pub fn read_wav(filename: &str) -> Result<AudioData, DawrError> {
+    let mut reader = try!(hound::WavReader::open(filename));
+    let spec = reader.spec();
+
+    let interleaved : Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => {
+            try!(reader.samples::<f32>().collect())
+        },
+        hound::SampleFormat::Int => {
+            let full_scale = 2.0_f32.powi(spec.bits_per_sample as i32 - 1);
+            try!(reader.samples::<i32>().map(|s| s.map(|s| s as f32 / full_scale)).collect())
+        }
+    };
+
+    Ok(AudioData { channels: deinterleave(&interleaved, spec.channels as usize), sample_rate: spec.sample_rate })
+}
+
+fn deinterleave(interleaved: &[f32], count: usize) -> Vec<Vec<f32>> {
+    let mut channels = vec![Vec::<f32>::with_capacity(interleaved.len() / count); count];
+    for frame in interleaved.chunks(count).filter(|frame| frame.len() == count) {
+        for (channel, sample) in frame.iter().enumerate() {
+            channels[channel].push(*sample);
+        }
+    }
+    channels
+}
+
+#[cfg(feature = "flac")]
+pub fn read_flac(filename: &str) -> Result<AudioData, DawrError> {
+    let mut reader = try!(claxon::FlacReader::open(filename));
+    let info = reader.streaminfo();
+    let full_scale = 2.0_f32.powi(info.bits_per_sample as i32 - 1);
+    let interleaved : Vec<f32> = try!(reader.samples().map(|s| s.map(|s| s as f32 / full_scale)).collect());
+    Ok(AudioData { channels: deinterleave(&interleaved, info.channels as usize), sample_rate: info.sample_rate })
+}
+
+#[cfg(feature = "vorbis")]
+pub fn read_ogg_vorbis(filename: &str) -> Result<AudioData, DawrError> {
+    let mut reader = try!(lewton::inside_ogg::OggStreamReader::new(try!(File::open(filename))));
+    let mut channels = vec![Vec::<f32>::new(); reader.ident_hdr.audio_channels as usize];
+    while let Some(packet) = try!(reader.read_dec_packet_generic::<Vec<Vec<f32>>>()) {
+        for (channel, samples) in channels.iter_mut().zip(packet.iter()) {
+            channel.extend_from_slice(samples);
+        }
+    }
+    Ok(AudioData { channels, sample_rate: reader.ident_hdr.audio_sample_rate })
+}
+
+// The channel count and sample rate are taken from the first frame.
+#[cfg(feature = "mp3")]
+pub fn read_mp3(filename: &str) -> Result<AudioData, DawrError> {
+    let mut decoder = minimp3::Decoder::new(try!(File::open(filename)));
+    let mut interleaved = Vec::<f32>::new();
+    let mut format = None;
+    loop {
+        match decoder.next_frame() {
+            Ok(frame) => {
+                if format.is_none() {
+                    format = Some((frame.channels, frame.sample_rate as u32));
+                }
+                interleaved.extend(frame.data.iter().map(|&s| s as f32 / 32768.0));
+            },
+            Err(minimp3::Error::Eof) => break,
+            Err(error) => return Err(DawrError::from(error))
+        }
+    }
+    match format {
+        Some((channels, sample_rate)) => Ok(AudioData { channels: deinterleave(&interleaved, channels), sample_rate }),
+        None => Err(DawrError::Decode(format!("{} has no MP3 frames", filename)))
+    }
+}
+
+// Picks a decoder from the file's extension: .wav, or .flac, .ogg and .mp3 when the flac, vorbis
+// and mp3 features are enabled.
+pub fn read_audio_file(filename: &str) -> Result<AudioData, DawrError> {
+    let extension = Path::new(filename).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    match extension.as_ref().map(|e| e.as_str()) {
+        Some("wav") | Some("wave") => read_wav(filename),
+        #[cfg(feature = "flac")]
+        Some("flac") => read_flac(filename),
+        #[cfg(feature = "vorbis")]
+        Some("ogg") | Some("oga") => read_ogg_vorbis(filename),
+        #[cfg(feature = "mp3")]
+        Some("mp3") => read_mp3(filename),
+        _ => Err(DawrError::UnsupportedFormat(filename.to_string()))
+    }
+}
+
+// Like load_wav_to_stereo_at_rate, for any format read_audio_file supports.
+pub fn load_audio_to_stereo_at_rate(filename: &str, sample_rate: u32) -> (Vec<f32>, Vec<f32>) {
+    try_load_audio_to_stereo_at_rate(filename, sample_rate).unwrap_or_else(|error| panic!("{}", error))
+}
+
+pub fn try_load_audio_to_stereo_at_rate(filename: &str, sample_rate: u32) -> Result<(Vec<f32>, Vec<f32>), DawrError> {
+    Ok(try!(read_audio_file(filename)).resampled(sample_rate, ResampleQuality::Good).to_stereo())
+}
+
+pub fn load_wav_to_stereo(filename: &str) -> (Vec<f32>, Vec<f32>) {
+    load_wav_to_stereo_at_rate(filename, consts::SAMPLE_RATE)
+}
+
+// Files at other rates are resampled to `sample_rate`, which should be the Clock's.
+pub fn load_wav_to_stereo_at_rate(filename: &str, sample_rate: u32) -> (Vec<f32>, Vec<f32>) {
+    load_wav_to_stereo_with_quality(filename, sample_rate, ResampleQuality::Good)
+}
 
-    assert!(left.len() == right.len());
+pub fn load_wav_to_stereo_with_quality(filename: &str, sample_rate: u32, quality: ResampleQuality) -> (Vec<f32>, Vec<f32>) {
+    try_load_wav_to_stereo_with_quality(filename, sample_rate, quality).unwrap_or_else(|error| panic!("{}", error))
+}
+
+pub fn try_load_wav_to_stereo_at_rate(filename: &str, sample_rate: u32) -> Result<(Vec<f32>, Vec<f32>), DawrError> {
+    try_load_wav_to_stereo_with_quality(filename, sample_rate, ResampleQuality::Good)
+}
+
+pub fn try_load_wav_to_stereo_with_quality(filename: &str, sample_rate: u32, quality: ResampleQuality) -> Result<(Vec<f32>, Vec<f32>), DawrError> {
+    Ok(try!(read_wav(filename)).resampled(sample_rate, quality).to_stereo())
+}
+
+// Mixes all of the file's channels down to one. The sample rate is left as-is, which is what you
+// want for e.g. single-cycle waveforms where only the shape matters.
+pub fn load_wav_to_mono(filename: &str) -> Vec<f32> {
+    try_load_wav_to_mono(filename).unwrap_or_else(|error| panic!("{}", error))
+}
 
-    (left, right)
+pub fn try_load_wav_to_mono(filename: &str) -> Result<Vec<f32>, DawrError> {
+    Ok(try!(read_wav(filename)).to_mono())
 }
 
 pub fn save_stereo_to_wav(left: &Vec<f32>, right: &Vec<f32>, filename: &str) {
-    assert!(left.len() == right.len());
+    save_stereo_to_wav_at_rate(left, right, filename, consts::SAMPLE_RATE)
+}
+
+pub fn save_stereo_to_wav_at_rate(left: &Vec<f32>, right: &Vec<f32>, filename: &str, sample_rate: u32) {
+    save_stereo_to_wav_with_settings(left, right, filename, &ExportSettings::new(sample_rate)).unwrap_or_else(|error| panic!("{}", error));
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Int16,
+    Int24,
+    Int32,
+    Float32
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dither {
+    None,
+    // Triangular dither of +-1 LSB, which makes the quantization noise independent of the signal.
+    Tpdf,
+    // TPDF dither with the noise pushed up towards high frequencies, where it's harder to hear.
+    NoiseShaped
+}
+
+pub struct ExportSettings {
+    pub sample_rate: u32,
+    pub format: ExportFormat,
+    // Only used for the integer formats.
+    pub dither: Dither
+}
+
+impl ExportSettings {
+    // 16-bit without dither.
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate, format: ExportFormat::Int16, dither: Dither::None }
+    }
+}
 
+pub struct ExportReport {
+    // Samples that didn't fit. In integer formats that's those that had to be saturated by more
+    // than one step, so +1.0 (one step above the largest integer) and dither nudging a sample
+    // just over don't count. In float it's those outside [-1, 1], which are left as they are.
+    pub clipped_samples: usize,
+    // The largest absolute sample value, before saturation.
+    pub peak: f32
+}
+
+impl ExportReport {
+    pub fn peak_decibels(&self) -> f32 {
+        gain_to_decibels(self.peak)
+    }
+}
+
+// Turns samples into integers, one channel at a time.
+struct Quantizer {
+    full_scale: f64,
+    dither: Dither,
+    // The last two quantization errors, for noise shaping.
+    errors: [f64; 2],
+    // How many samples were more than one step out of range.
+    clipped: usize
+}
+
+impl Quantizer {
+    fn new(bits: u16, dither: Dither) -> Self {
+        Self { full_scale: 2.0_f64.powi(bits as i32 - 1), dither, errors: [0.0, 0.0], clipped: 0 }
+    }
+
+    fn quantize(&mut self, sample: f32) -> i32 {
+        let mut value = sample as f64 * self.full_scale;
+        if self.dither == Dither::NoiseShaped {
+            // Second order error feedback, so the noise is shaped by (1 - z^-1)^2.
+            value += -2.0 * self.errors[0] + self.errors[1];
+        }
+        let noise = match self.dither {
+            Dither::None => 0.0,
+            Dither::Tpdf | Dither::NoiseShaped => rand::random::<f64>() - rand::random::<f64>()
+        };
+        let quantized = (value + noise).round();
+        self.errors = [quantized - value, self.errors[0]];
+        let saturated = quantized.max(-self.full_scale).min(self.full_scale - 1.0);
+        if (saturated - quantized).abs() > 1.0 {
+            self.clipped += 1;
+        }
+        saturated as i32
+    }
+}
+
+fn measure_levels(left: &Vec<f32>, right: &Vec<f32>) -> Result<ExportReport, DawrError> {
+    if left.len() != right.len() {
+        return Err(DawrError::InvalidArgument(format!(
+            "the left channel has {} samples but the right has {}", left.len(), right.len()
+        )));
+    }
+
+    let mut report = ExportReport { clipped_samples: 0, peak: 0.0 };
+    for sample in left.iter().chain(right.iter()) {
+        report.peak = report.peak.max(sample.abs());
+        if sample.abs() > 1.0 {
+            report.clipped_samples += 1;
+        }
+    }
+    Ok(report)
+}
+
+pub fn save_stereo_to_wav_with_settings(left: &Vec<f32>, right: &Vec<f32>, filename: &str, settings: &ExportSettings) -> Result<ExportReport, DawrError> {
+    let report = try!(measure_levels(left, right));
+
+    let (bits, sample_format) = match settings.format {
+        ExportFormat::Int16 => (16, hound::SampleFormat::Int),
+        ExportFormat::Int24 => (24, hound::SampleFormat::Int),
+        ExportFormat::Int32 => (32, hound::SampleFormat::Int),
+        ExportFormat::Float32 => (32, hound::SampleFormat::Float)
+    };
     let spec = hound::WavSpec {
         channels: 2,
-        sample_rate: 44100,
-        bits_per_sample: 16,
-        sample_format: hound::SampleFormat::Int,
+        sample_rate: settings.sample_rate,
+        bits_per_sample: bits,
+        sample_format,
     };
-    let mut writer = hound::WavWriter::create(filename, spec).unwrap();
+    let mut writer = try!(hound::WavWriter::create(filename, spec));
+
+    let mut quantizers = [Quantizer::new(bits, settings.dither), Quantizer::new(bits, settings.dither)];
 
     for i in 0..left.len() {
-        let amplitude = i16::MAX as f32;
-        writer.write_sample((left[i] * amplitude) as i16).unwrap();
-        writer.write_sample((right[i] * amplitude) as i16).unwrap();
+        for (channel, sample) in [left[i], right[i]].iter().enumerate() {
+            if settings.format == ExportFormat::Float32 {
+                try!(writer.write_sample(*sample));
+            } else {
+                try!(writer.write_sample(quantizers[channel].quantize(*sample)));
+            }
+        }
+    }
+
+    try!(writer.finalize());
+    if settings.format == ExportFormat::Float32 {
+        Ok(report)
+    } else {
+        Ok(ExportReport { clipped_samples: quantizers[0].clipped + quantizers[1].clipped, ..report })
     }
 }
+
+// Metadata written to FLAC files as Vorbis comments. `other` holds any extra fields, like
+// ("GENRE", "Future Bass").
+#[derive(Default)]
+pub struct FlacTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub bpm: Option<f64>,
+    pub other: Vec<(String, String)>
+}
+
+// Saves losslessly compressed FLAC. Only the Int16 and Int24 formats are supported.
+pub fn save_stereo_to_flac(left: &Vec<f32>, right: &Vec<f32>, filename: &str, settings: &ExportSettings, tags: &FlacTags) -> Result<ExportReport, DawrError> {
+    let report = try!(measure_levels(left, right));
+
+    let bits = match settings.format {
+        ExportFormat::Int16 => 16,
+        ExportFormat::Int24 => 24,
+        _ => return Err(DawrError::InvalidArgument("FLAC only supports 16 and 24-bit integer samples".to_string()))
+    };
+    if settings.sample_rate == 0 || settings.sample_rate >= 1 << 20 {
+        return Err(DawrError::InvalidArgument(format!("FLAC can't store a sample rate of {}", settings.sample_rate)));
+    }
+
+    let mut clipped_samples = 0;
+    let channels : Vec<Vec<i32>> = [left, right].iter().map(|samples| {
+        let mut quantizer = Quantizer::new(bits, settings.dither);
+        let quantized = samples.iter().map(|&sample| quantizer.quantize(sample)).collect();
+        clipped_samples += quantizer.clipped;
+        quantized
+    }).collect();
+    let report = ExportReport { clipped_samples, ..report };
+
+    let mut comments = Vec::<String>::new();
+    if let Some(ref title) = tags.title {
+        comments.push(format!("TITLE={}", title));
+    }
+    if let Some(ref artist) = tags.artist {
+        comments.push(format!("ARTIST={}", artist));
+    }
+    if let Some(bpm) = tags.bpm {
+        comments.push(format!("BPM={}", bpm));
+    }
+    for &(ref field, ref value) in tags.other.iter() {
+        comments.push(format!("{}={}", field, value));
+    }
+
+    let mut file = try!(File::create(filename));
+    try!(file.write_all(&flac::encode(&channels, settings.sample_rate, bits as u32, &comments)));
+    Ok(report)
+}
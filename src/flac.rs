@@ -0,0 +1,512 @@
+// A small FLAC encoder. It only uses the fixed predictors, not LPC, so files come out a bit larger
+// than the reference encoder's, but they're lossless and any decoder can read them.
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_PARTITION_ORDER: usize = 8;
+// Rice parameter 15 is the escape code, which we never use.
+const MAX_RICE_PARAMETER: u32 = 14;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    bits: u32
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), accumulator: 0, bits: 0 }
+    }
+
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.accumulator >> self.bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    // `zeros` zero bits followed by a one.
+    fn write_unary(&mut self, mut zeros: u64) {
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    fn align(&mut self) {
+        if self.bits > 0 {
+            let padding = 8 - self.bits;
+            self.write(0, padding);
+        }
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// STREAMINFO stores an MD5 of the raw samples so decoders can check the file.
+fn md5(message: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+        5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+        4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+        6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21
+    ];
+    let constants : Vec<u32> = (0..64).map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32).collect();
+
+    let mut data = message.to_vec();
+    data.push(0x80);
+    while data.len() % 64 != 56 {
+        data.push(0);
+    }
+    let length = (message.len() as u64).wrapping_mul(8);
+    for i in 0..8 {
+        data.push((length >> (8 * i)) as u8);
+    }
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in data.chunks(64) {
+        let words : Vec<u32> = chunk.chunks(4).map(|b| {
+            b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24
+        }).collect();
+        let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16)
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(constants[i]).wrapping_add(words[g]).rotate_left(SHIFTS[i]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0u8; 16];
+    for i in 0..16 {
+        digest[i] = (state[i / 4] >> (8 * (i % 4))) as u8;
+    }
+    digest
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// The residual of fixed predictor `order` (0 to 4), for the samples after the warm-up ones.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len()).map(|i| {
+        let s = |k: usize| samples[i - k];
+        match order {
+            0 => s(0),
+            1 => s(0) - s(1),
+            2 => s(0) - 2 * s(1) + s(2),
+            3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+            _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4)
+        }
+    }).collect()
+}
+
+struct RiceCoding {
+    partition_order: usize,
+    parameters: Vec<u32>,
+    bits: u64
+}
+
+// Picks the partition order and per-partition Rice parameters that make the residual smallest.
+fn plan_rice_coding(residual: &[i64], block_size: usize, predictor_order: usize) -> RiceCoding {
+    let mut max_order = 0;
+    while max_order < MAX_PARTITION_ORDER
+        && block_size % (1 << (max_order + 1)) == 0
+        && (block_size >> (max_order + 1)) > predictor_order {
+        max_order += 1;
+    }
+
+    // The sum of the zigzagged residual and the number of values in each of the smallest
+    // partitions. Adjacent pairs are merged to get the next partition order down.
+    let partition_size = block_size >> max_order;
+    let mut sums : Vec<(u64, u64)> = (0..(1 << max_order)).map(|p| {
+        let start = if p == 0 { 0 } else { p * partition_size - predictor_order };
+        let end = (p + 1) * partition_size - predictor_order;
+        (residual[start..end].iter().map(|&r| zigzag(r)).sum::<u64>(), (end - start) as u64)
+    }).collect();
+
+    let mut best : Option<RiceCoding> = None;
+    for order in (0..(max_order + 1)).rev() {
+        if order < max_order {
+            sums = sums.chunks(2).map(|pair| (pair[0].0 + pair[1].0, pair[0].1 + pair[1].1)).collect();
+        }
+        let mut bits = 0u64;
+        let mut parameters = Vec::<u32>::new();
+        for &(sum, count) in sums.iter() {
+            // Estimates the size of each parameter's encoding, then keeps the smallest.
+            let (parameter, partition_bits) = (0..(MAX_RICE_PARAMETER + 1))
+                .map(|k| (k, count * (k as u64 + 1) + (sum >> k)))
+                .min_by_key(|&(_, bits)| bits)
+                .unwrap();
+            parameters.push(parameter);
+            bits += 4 + partition_bits;
+        }
+        if best.as_ref().map_or(true, |b| bits <= b.bits) {
+            best = Some(RiceCoding { partition_order: order, parameters, bits });
+        }
+    }
+    best.unwrap()
+}
+
+enum Subframe {
+    Constant(i64),
+    Verbatim,
+    Fixed(usize, Vec<i64>, RiceCoding)
+}
+
+// Picks the smallest encoding of one channel of a block, and returns it with its size in bits.
+fn plan_subframe(samples: &[i64], bits_per_sample: u32) -> (Subframe, u64) {
+    if samples.iter().all(|&s| s == samples[0]) {
+        return (Subframe::Constant(samples[0]), 8 + bits_per_sample as u64);
+    }
+
+    let mut best = (Subframe::Verbatim, 8 + bits_per_sample as u64 * samples.len() as u64);
+    for order in 0..5 {
+        if samples.len() <= order {
+            break;
+        }
+        let residual = fixed_residual(samples, order);
+        let coding = plan_rice_coding(&residual, samples.len(), order);
+        let bits = 8 + bits_per_sample as u64 * order as u64 + 6 + coding.bits;
+        if bits < best.1 {
+            best = (Subframe::Fixed(order, residual, coding), bits);
+        }
+    }
+    best
+}
+
+fn write_subframe(writer: &mut BitWriter, samples: &[i64], subframe: &Subframe, bits_per_sample: u32) {
+    match *subframe {
+        Subframe::Constant(value) => {
+            writer.write(0b0_000000_0, 8);
+            writer.write_signed(value, bits_per_sample);
+        },
+        Subframe::Verbatim => {
+            writer.write(0b0_000001_0, 8);
+            for &sample in samples {
+                writer.write_signed(sample, bits_per_sample);
+            }
+        },
+        Subframe::Fixed(order, ref residual, ref coding) => {
+            writer.write(0b0_001000_0 | (order as u64) << 1, 8);
+            for &sample in &samples[..order] {
+                writer.write_signed(sample, bits_per_sample);
+            }
+            // Partitioned Rice coding with 4-bit parameters.
+            writer.write(0b00, 2);
+            writer.write(coding.partition_order as u64, 4);
+            let partition_size = samples.len() >> coding.partition_order;
+            let mut start = 0;
+            for (p, &parameter) in coding.parameters.iter().enumerate() {
+                let end = (p + 1) * partition_size - order;
+                writer.write(parameter as u64, 4);
+                for &value in &residual[start..end] {
+                    let value = zigzag(value);
+                    writer.write_unary(value >> parameter);
+                    writer.write(value, parameter);
+                }
+                start = end;
+            }
+        }
+    }
+}
+
+// FLAC's variant of UTF-8, which can encode up to 36 bits.
+fn write_utf8(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write(value, 8);
+        return;
+    }
+    let mut continuation_bytes = 1;
+    while value >= 1u64 << (6 * continuation_bytes + 6 - continuation_bytes) {
+        continuation_bytes += 1;
+    }
+    // The lead byte has a one bit per byte in the sequence, then a zero, then the top bits.
+    let prefix = (0xFF00u64 >> (continuation_bytes + 1)) & 0xFF;
+    writer.write(prefix | (value >> (6 * continuation_bytes)), 8);
+    for i in (0..continuation_bytes).rev() {
+        writer.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+fn write_frame(output: &mut Vec<u8>, channels: &[Vec<i64>], start: usize, length: usize, frame_number: u64, bits_per_sample: u32) {
+    let block : Vec<&[i64]> = channels.iter().map(|channel| &channel[start..start + length]).collect();
+
+    // Each channel on its own (with its size in bits), plus for stereo the side channel, which
+    // needs one more bit, and the mid channel. Mid and side are encoded as (L + R) >> 1 and L - R.
+    let mut plans : Vec<(Subframe, u64)> = block.iter().map(|samples| plan_subframe(samples, bits_per_sample)).collect();
+    let mut assignment = channels.len() as u64 - 1;
+    let mut decorrelated : Vec<Vec<i64>> = block.iter().map(|samples| samples.to_vec()).collect();
+    let mut sizes = vec![bits_per_sample; channels.len()];
+    if channels.len() == 2 {
+        let side : Vec<i64> = (0..length).map(|i| block[0][i] - block[1][i]).collect();
+        let mid : Vec<i64> = (0..length).map(|i| (block[0][i] + block[1][i]) >> 1).collect();
+        let side_plan = plan_subframe(&side, bits_per_sample + 1);
+        let mid_plan = plan_subframe(&mid, bits_per_sample);
+
+        let left_right = plans[0].1 + plans[1].1;
+        let left_side = plans[0].1 + side_plan.1;
+        let right_side = plans[1].1 + side_plan.1;
+        let mid_side = mid_plan.1 + side_plan.1;
+        let smallest = *[left_right, left_side, right_side, mid_side].iter().min().unwrap();
+
+        if smallest == mid_side {
+            assignment = 0b1010;
+            plans = vec![mid_plan, side_plan];
+            decorrelated = vec![mid, side];
+            sizes = vec![bits_per_sample, bits_per_sample + 1];
+        } else if smallest == left_side {
+            assignment = 0b1000;
+            plans = vec![plans.remove(0), side_plan];
+            decorrelated = vec![decorrelated.remove(0), side];
+            sizes = vec![bits_per_sample, bits_per_sample + 1];
+        } else if smallest == right_side {
+            assignment = 0b1001;
+            plans = vec![side_plan, plans.remove(1)];
+            decorrelated = vec![side, decorrelated.remove(1)];
+            sizes = vec![bits_per_sample + 1, bits_per_sample];
+        }
+    }
+
+    let mut writer = BitWriter::new();
+    // Sync code, reserved bit and fixed-blocksize strategy.
+    writer.write(0b11111111111110_0_0, 16);
+    // The block size minus one is stored as 16 bits after the frame number, and the sample rate
+    // comes from STREAMINFO.
+    writer.write(0b0111, 4);
+    writer.write(0b0000, 4);
+    writer.write(assignment, 4);
+    let size_code = match bits_per_sample {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        24 => 0b110,
+        _ => 0b000
+    };
+    writer.write(size_code, 3);
+    writer.write(0, 1);
+    write_utf8(&mut writer, frame_number);
+    writer.write(length as u64 - 1, 16);
+    let crc = crc8(&writer.bytes);
+    writer.write(crc as u64, 8);
+
+    for (channel, &(ref subframe, _)) in plans.iter().enumerate() {
+        write_subframe(&mut writer, &decorrelated[channel], subframe, sizes[channel]);
+    }
+    writer.align();
+    let crc = crc16(&writer.bytes);
+    writer.write(crc as u64, 16);
+
+    output.extend_from_slice(&writer.bytes);
+}
+
+// Encodes whole channels of integer samples as a FLAC file. `comments` are Vorbis comments like
+// "TITLE=Demo".
+pub fn encode(channels: &[Vec<i32>], sample_rate: u32, bits_per_sample: u32, comments: &[String]) -> Vec<u8> {
+    assert!(channels.len() >= 1 && channels.len() <= 8);
+    assert!(channels.iter().all(|channel| channel.len() == channels[0].len()));
+    assert!(sample_rate >= 1 && sample_rate < (1 << 20));
+    assert!(bits_per_sample >= 4 && bits_per_sample <= 24);
+
+    let total_samples = channels[0].len();
+    let wide : Vec<Vec<i64>> = channels.iter().map(|channel| channel.iter().map(|&s| s as i64).collect()).collect();
+
+    let mut frames = Vec::<u8>::new();
+    let mut frame_sizes = Vec::<usize>::new();
+    let mut start = 0;
+    while start < total_samples {
+        let length = BLOCK_SIZE.min(total_samples - start);
+        let before = frames.len();
+        write_frame(&mut frames, &wide, start, length, (start / BLOCK_SIZE) as u64, bits_per_sample);
+        frame_sizes.push(frames.len() - before);
+        start += length;
+    }
+
+    let bytes_per_sample = ((bits_per_sample + 7) / 8) as usize;
+    let mut raw = Vec::<u8>::with_capacity(total_samples * channels.len() * bytes_per_sample);
+    for i in 0..total_samples {
+        for channel in channels.iter() {
+            for b in 0..bytes_per_sample {
+                raw.push((channel[i] >> (8 * b)) as u8);
+            }
+        }
+    }
+
+    let mut writer = BitWriter::new();
+    writer.bytes.extend_from_slice(b"fLaC");
+
+    // STREAMINFO. The minimum block size doesn't count the last block, so it's always BLOCK_SIZE.
+    writer.write(0, 1);
+    writer.write(0, 7);
+    writer.write(34, 24);
+    writer.write(BLOCK_SIZE as u64, 16);
+    writer.write(BLOCK_SIZE as u64, 16);
+    writer.write(frame_sizes.iter().cloned().min().unwrap_or(0) as u64, 24);
+    writer.write(frame_sizes.iter().cloned().max().unwrap_or(0) as u64, 24);
+    writer.write(sample_rate as u64, 20);
+    writer.write(channels.len() as u64 - 1, 3);
+    writer.write(bits_per_sample as u64 - 1, 5);
+    writer.write((total_samples as u64) >> 32, 4);
+    writer.write(total_samples as u64 & 0xFFFFFFFF, 32);
+    writer.bytes.extend_from_slice(&md5(&raw));
+
+    // VORBIS_COMMENT, the last metadata block. Its lengths are little-endian, unlike the rest.
+    let vendor = "DAWr";
+    let mut comment_block = Vec::<u8>::new();
+    let push_le = |block: &mut Vec<u8>, value: u32| {
+        for i in 0..4 {
+            block.push((value >> (8 * i)) as u8);
+        }
+    };
+    push_le(&mut comment_block, vendor.len() as u32);
+    comment_block.extend_from_slice(vendor.as_bytes());
+    push_le(&mut comment_block, comments.len() as u32);
+    for comment in comments {
+        push_le(&mut comment_block, comment.len() as u32);
+        comment_block.extend_from_slice(comment.as_bytes());
+    }
+    writer.write(1, 1);
+    writer.write(4, 7);
+    writer.write(comment_block.len() as u64, 24);
+    writer.bytes.extend_from_slice(&comment_block);
+
+    writer.bytes.extend_from_slice(&frames);
+    writer.bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn md5_matches_known_digests() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(&md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(hex(&md5(b"The quick brown fox jumps over the lazy dog")), "9e107d9d372bb6826bd81d3542a419d6");
+        // Longer than one 64-byte block.
+        let digits = b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        assert_eq!(hex(&md5(digits)), "57edf4a22be3c955ac49da2e2107b67a");
+    }
+
+    // A fixed pseudo-random sequence, so the tests are repeatable.
+    fn noise(length: usize, amplitude: i32, seed: u64) -> Vec<i32> {
+        let mut state = seed;
+        (0..length).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) % (2 * amplitude as u64 + 1)) as i32 - amplitude
+        }).collect()
+    }
+
+    fn sine(length: usize, amplitude: f64) -> Vec<i32> {
+        (0..length).map(|i| (amplitude * (2.0 * PI * i as f64 / 50.0).sin()).round() as i32).collect()
+    }
+
+    fn add(a: &[i32], b: &[i32]) -> Vec<i32> {
+        a.iter().zip(b.iter()).map(|(x, y)| x + y).collect()
+    }
+
+    // Stereo signals that each encode best with a different channel assignment: independent left
+    // and right, left/side, right/side and mid/side. Returns them with the assignment expected.
+    fn stereo_signals(length: usize, bits: u32) -> Vec<(u64, Vec<i32>, Vec<i32>)> {
+        // Residuals bigger than the largest Rice parameter handles are stored verbatim, which
+        // would hide the differences, so only the independent pair uses the full range.
+        let smooth = sine(length, 16384.0);
+        let rough = add(&smooth, &noise(length, 1024, 1));
+        let shared = noise(length, 1024, 2);
+        vec![
+            (0b0001, smooth.clone(), noise(length, 1 << (bits - 2), 3)),
+            (0b1000, smooth.clone(), rough.clone()),
+            (0b1001, rough, smooth),
+            (0b1010, shared.clone(), shared)
+        ]
+    }
+
+    #[test]
+    fn frames_use_every_channel_assignment() {
+        for &bits in [16, 24].iter() {
+            for (assignment, left, right) in stereo_signals(BLOCK_SIZE, bits) {
+                let wide : Vec<Vec<i64>> = [left, right].iter().map(|c| c.iter().map(|&s| s as i64).collect()).collect();
+                let mut frame = Vec::<u8>::new();
+                write_frame(&mut frame, &wide, 0, BLOCK_SIZE, 0, bits);
+                assert_eq!((frame[3] >> 4) as u64, assignment);
+            }
+        }
+    }
+
+    #[cfg(feature = "flac")]
+    #[test]
+    fn encode_round_trips_through_read_flac() {
+        use files;
+        use std::env;
+        use std::fs;
+
+        for &bits in [16, 24].iter() {
+            for &length in [0, 1, BLOCK_SIZE - 1, BLOCK_SIZE, BLOCK_SIZE + 1, 3 * BLOCK_SIZE + 17].iter() {
+                for (assignment, left, right) in stereo_signals(length, bits) {
+                    let path = env::temp_dir().join(format!("dawr-flac-test-{}-{}-{}.flac", bits, length, assignment));
+                    let filename = path.to_str().unwrap();
+                    let encoded = encode(&[left.clone(), right.clone()], 44100, bits, &["TITLE=Test".to_string()]);
+                    fs::write(&path, &encoded).unwrap();
+
+                    let decoded = files::read_flac(filename).unwrap();
+                    fs::remove_file(&path).unwrap();
+
+                    let full_scale = 2.0_f32.powi(bits as i32 - 1);
+                    let to_ints = |samples: &Vec<f32>| samples.iter().map(|&s| (s * full_scale) as i32).collect::<Vec<i32>>();
+                    assert_eq!(decoded.sample_rate, 44100);
+                    assert_eq!(decoded.channels.len(), 2);
+                    assert_eq!(to_ints(&decoded.channels[0]), left);
+                    assert_eq!(to_ints(&decoded.channels[1]), right);
+                }
+            }
+        }
+    }
+}
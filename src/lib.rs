@@ -1,6 +1,12 @@
 extern crate rand;
 extern crate portaudio;
 extern crate hound;
+#[cfg(feature = "flac")]
+extern crate claxon;
+#[cfg(feature = "vorbis")]
+extern crate lewton;
+#[cfg(feature = "mp3")]
+extern crate minimp3;
 
 pub mod clock;
 pub mod consts;
@@ -11,6 +17,10 @@ pub mod effects;
 pub mod conversions;
 pub mod sampler;
 pub mod files;
+pub mod signals;
+pub mod error;
+mod flac;
+mod fft;
 
 use clock::*;
 use device::*;
@@ -21,10 +31,11 @@ pub fn render_audio(clock: Rc<Clock>, master: Rc<StereoEmitter>, length: usize)
     let mut right = Vec::<f32>::new();
 
     while left.len() < length {
-        assert!(master.output().0.len() == master.output().1.len());
-        for i in 0..master.output().0.len() {
-            left.push(master.output().0[i]);
-            right.push(master.output().1[i]);
+        {
+            let (chunk_left, chunk_right) = master.output();
+            assert!(chunk_left.len() == chunk_right.len());
+            left.extend_from_slice(&chunk_left);
+            right.extend_from_slice(&chunk_right);
         }
         clock.increment();
     }
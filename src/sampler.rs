@@ -2,7 +2,7 @@ use std::rc::Rc;
 use device::*;
 use clock::Clock;
 use events::EventSource;
-use consts;
+use error::DawrError;
 use std::cell::*;
 
 pub enum SamplerEvent {
@@ -25,11 +25,19 @@ struct SamplerState {
 
 impl Sampler {
     pub fn new(clock: Rc<Clock>, sampler_events: Rc<EventSource<SamplerEvent>>, left_samples: Vec<f32>, right_samples: Vec<f32>) -> Rc<Sampler> {
-        assert!(left_samples.len() == right_samples.len());
-        Rc::new(Self {
+        Self::try_new(clock, sampler_events, left_samples, right_samples).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(clock: Rc<Clock>, sampler_events: Rc<EventSource<SamplerEvent>>, left_samples: Vec<f32>, right_samples: Vec<f32>) -> Result<Rc<Sampler>, DawrError> {
+        if left_samples.len() != right_samples.len() {
+            return Err(DawrError::InvalidArgument(format!(
+                "the left channel has {} samples but the right has {}", left_samples.len(), right_samples.len()
+            )));
+        }
+        Ok(Rc::new(Self {
             device: StereoStateContainer::new(clock, SamplerState { playing: false, position: 0.0, playspeed: 1.0 }),
             left_samples, right_samples, sampler_events
-        })
+        }))
     }
 }
 
@@ -45,7 +53,7 @@ impl StereoEmitter for Sampler {
             let events = self.sampler_events.events_this_chunk();
             let mut cursor = 0;
 
-            for i in 0..consts::CHUNK_SIZE {
+            for i in 0..self.device.chunk_size() {
 
                 while cursor < events.len() && events[cursor].0 == self.device.time() + i as u64 {
                     match events[cursor].1 {
@@ -0,0 +1,185 @@
+use device::*;
+use clock::Clock;
+use std::rc::Rc;
+use std::cell::*;
+
+// Applies a function to every sample of a mono signal.
+pub struct MonoMap {
+    device: MonoStateContainer<()>,
+    input: Rc<MonoEmitter>,
+    f: Box<Fn(f32) -> f32>
+}
+
+impl MonoMap {
+    pub fn new<F: Fn(f32) -> f32 + 'static>(clock: Rc<Clock>, input: Rc<MonoEmitter>, f: F) -> Rc<Self> {
+        Rc::new(Self { device: MonoStateContainer::<()>::new(clock, ()), input, f: Box::new(f) })
+    }
+}
+
+impl MonoEmitter for MonoMap {
+    fn output(&self) -> Ref<Vec<f32>> {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let input = self.input.output();
+            let mut output = self.device.borrow_to_modify();
+
+            for i in 0..self.device.chunk_size() {
+                output[i] = (self.f)(input[i]);
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Combines two mono signals sample by sample with a function.
+pub struct MonoZip {
+    device: MonoStateContainer<()>,
+    a: Rc<MonoEmitter>,
+    b: Rc<MonoEmitter>,
+    f: Box<Fn(f32, f32) -> f32>
+}
+
+impl MonoZip {
+    pub fn new<F: Fn(f32, f32) -> f32 + 'static>(clock: Rc<Clock>, a: Rc<MonoEmitter>, b: Rc<MonoEmitter>, f: F) -> Rc<Self> {
+        Rc::new(Self { device: MonoStateContainer::<()>::new(clock, ()), a, b, f: Box::new(f) })
+    }
+}
+
+impl MonoEmitter for MonoZip {
+    fn output(&self) -> Ref<Vec<f32>> {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let a = self.a.output();
+            let b = self.b.output();
+            let mut output = self.device.borrow_to_modify();
+
+            for i in 0..self.device.chunk_size() {
+                output[i] = (self.f)(a[i], b[i]);
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Sums any number of mono signals.
+pub struct MonoSum {
+    device: MonoStateContainer<()>,
+    inputs: Vec<Rc<MonoEmitter>>
+}
+
+impl MonoSum {
+    pub fn new(clock: Rc<Clock>, inputs: Vec<Rc<MonoEmitter>>) -> Rc<Self> {
+        Rc::new(Self { device: MonoStateContainer::<()>::new(clock, ()), inputs })
+    }
+}
+
+impl MonoEmitter for MonoSum {
+    fn output(&self) -> Ref<Vec<f32>> {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let mut output = self.device.borrow_to_modify();
+            for i in 0..self.device.chunk_size() {
+                output[i] = 0.0;
+            }
+
+            for input in self.inputs.iter() {
+                let input = input.output();
+                for i in 0..self.device.chunk_size() {
+                    output[i] += input[i];
+                }
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Multiplies any number of mono signals.
+pub struct MonoProduct {
+    device: MonoStateContainer<()>,
+    inputs: Vec<Rc<MonoEmitter>>
+}
+
+impl MonoProduct {
+    pub fn new(clock: Rc<Clock>, inputs: Vec<Rc<MonoEmitter>>) -> Rc<Self> {
+        Rc::new(Self { device: MonoStateContainer::<()>::new(clock, ()), inputs })
+    }
+}
+
+impl MonoEmitter for MonoProduct {
+    fn output(&self) -> Ref<Vec<f32>> {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let mut output = self.device.borrow_to_modify();
+            for i in 0..self.device.chunk_size() {
+                output[i] = 1.0;
+            }
+
+            for input in self.inputs.iter() {
+                let input = input.output();
+                for i in 0..self.device.chunk_size() {
+                    output[i] *= input[i];
+                }
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
+// Builder helpers for composing control signals, e.g.
+//
+//     lfo.map_range(&clock, (-1.0, 1.0), (200.0, 2000.0))
+//
+// turns an LFO into a filter cutoff sweep.
+pub trait MonoSignal {
+    fn signal(&self) -> Rc<MonoEmitter>;
+
+    fn plus(&self, clock: &Rc<Clock>, other: Rc<MonoEmitter>) -> Rc<MonoEmitter> {
+        MonoSum::new(clock.clone(), vec![self.signal(), other])
+    }
+
+    fn times(&self, clock: &Rc<Clock>, other: Rc<MonoEmitter>) -> Rc<MonoEmitter> {
+        MonoProduct::new(clock.clone(), vec![self.signal(), other])
+    }
+
+    fn scale_offset(&self, clock: &Rc<Clock>, scale: f32, offset: f32) -> Rc<MonoEmitter> {
+        MonoMap::new(clock.clone(), self.signal(), move |x| x * scale + offset)
+    }
+
+    fn min_with(&self, clock: &Rc<Clock>, other: Rc<MonoEmitter>) -> Rc<MonoEmitter> {
+        MonoZip::new(clock.clone(), self.signal(), other, |a, b| a.min(b))
+    }
+
+    fn max_with(&self, clock: &Rc<Clock>, other: Rc<MonoEmitter>) -> Rc<MonoEmitter> {
+        MonoZip::new(clock.clone(), self.signal(), other, |a, b| a.max(b))
+    }
+
+    fn clamp_to(&self, clock: &Rc<Clock>, min: f32, max: f32) -> Rc<MonoEmitter> {
+        MonoMap::new(clock.clone(), self.signal(), move |x| x.max(min).min(max))
+    }
+
+    // Linearly maps `from.0` to `to.0` and `from.1` to `to.1`. Values outside `from` aren't clamped.
+    fn map_range(&self, clock: &Rc<Clock>, from: (f32, f32), to: (f32, f32)) -> Rc<MonoEmitter> {
+        let scale = (to.1 - to.0) / (from.1 - from.0);
+        MonoMap::new(clock.clone(), self.signal(), move |x| to.0 + (x - from.0) * scale)
+    }
+
+    fn map<F: Fn(f32) -> f32 + 'static>(&self, clock: &Rc<Clock>, f: F) -> Rc<MonoEmitter> {
+        MonoMap::new(clock.clone(), self.signal(), f)
+    }
+}
+
+impl<T: MonoEmitter + 'static> MonoSignal for Rc<T> {
+    fn signal(&self) -> Rc<MonoEmitter> {
+        self.clone()
+    }
+}
+
+impl MonoSignal for Rc<MonoEmitter> {
+    fn signal(&self) -> Rc<MonoEmitter> {
+        self.clone()
+    }
+}
@@ -4,8 +4,12 @@ use std::rc::Rc;
 use device::*;
 use clock::Clock;
 use events::EventSource;
-use consts;
+use effects::ConstSignal;
+use files;
+use error::DawrError;
+use fft::Dft;
 use std::cell::*;
+use std::f64::consts::PI;
 
 // Provides full-resolution down to 21 Hz for 44.1kHz sample rate.
 const WAVE_SAMPLES : usize = 2010;
@@ -15,6 +19,12 @@ pub enum NoteEvent {
     NoteOff
 }
 
+// Like NoteEvent, but with a note id so that overlapping notes can be told apart.
+pub enum PolyNoteEvent {
+    NoteOn(u32, f32),
+    NoteOff(u32)
+}
+
 #[derive(Clone)]
 pub struct Wave {
     samples: [f32; WAVE_SAMPLES]
@@ -53,34 +63,367 @@ impl Wave {
     pub fn zero() -> Self {
         Self { samples: [0.0; WAVE_SAMPLES] }
     }
+
+    pub fn sine() -> Self {
+        Self::from_fn(|phase| (2.0 * PI as f32 * phase).sin(), false)
+    }
+
+    // Evaluates `f` at phases in [0, 1) across one cycle. If `normalize` is set, the result is
+    // scaled so its peak is exactly 1.0.
+    pub fn from_fn<F: Fn(f32) -> f32>(f: F, normalize: bool) -> Self {
+        let mut samples = [0.0; WAVE_SAMPLES];
+        for i in 0..samples.len() {
+            samples[i] = f(i as f32 / WAVE_SAMPLES as f32);
+        }
+        let mut wave = Self { samples };
+        if normalize {
+            wave.normalize();
+        }
+        wave
+    }
+
+    // Sums sine harmonics given as (amplitude, phase in radians), starting at the fundamental.
+    pub fn from_harmonics(harmonics: &[(f32, f32)], normalize: bool) -> Self {
+        Self::from_fn(|phase| {
+            let mut sample = 0.0;
+            for (k, &(amplitude, offset)) in harmonics.iter().enumerate() {
+                sample += amplitude * (2.0 * PI as f32 * (k + 1) as f32 * phase + offset).sin();
+            }
+            sample
+        }, normalize)
+    }
+
+    fn normalize(&mut self) {
+        let peak = self.samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        if peak > 0.0 {
+            for sample in self.samples.iter_mut() {
+                *sample /= peak;
+            }
+        }
+    }
+
+    // Resamples a single cycle of any length to WAVE_SAMPLES.
+    pub fn from_samples(cycle: &[f32]) -> Self {
+        assert!(cycle.len() >= 1);
+        Self::resample(cycle, &Dft::new(cycle.len()), &Dft::new(WAVE_SAMPLES))
+    }
+
+    // Resamples through the spectrum, keeping only the harmonics both lengths can hold, so e.g. a
+    // 2048-sample Serum frame loses the harmonics that would alias at WAVE_SAMPLES instead of
+    // folding them back. `input_dft` is for cycle.len() samples and `output_dft` for WAVE_SAMPLES.
+    fn resample(cycle: &[f32], input_dft: &Dft, output_dft: &Dft) -> Self {
+        let len = cycle.len();
+        let n = WAVE_SAMPLES;
+        let mut samples = [0.0; WAVE_SAMPLES];
+        if len == n {
+            samples.copy_from_slice(cycle);
+            return Self { samples };
+        }
+
+        let input : Vec<(f64, f64)> = cycle.iter().map(|&sample| (sample as f64, 0.0)).collect();
+        let spectrum = input_dft.forward(&input);
+        // Only harmonics below the Nyquist frequency of both lengths are kept.
+        let harmonics = (len.min(n) - 1) / 2;
+        let mut kept = vec![(0.0, 0.0); n];
+        kept[0] = spectrum[0];
+        for k in 1..(harmonics + 1) {
+            kept[k] = spectrum[k];
+            kept[n - k] = spectrum[len - k];
+        }
+        for (sample, value) in samples.iter_mut().zip(output_dft.inverse(&kept)) {
+            *sample = (value.0 / len as f64) as f32;
+        }
+        Self { samples }
+    }
+
+    // Loads a WAV file containing exactly one cycle of a waveform.
+    pub fn from_wav(filename: &str) -> Self {
+        Self::try_from_wav(filename).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_from_wav(filename: &str) -> Result<Self, DawrError> {
+        let samples = try!(files::try_load_wav_to_mono(filename));
+        if samples.len() == 0 {
+            return Err(DawrError::InvalidArgument(format!("{} has no samples", filename)));
+        }
+        Ok(Self::from_samples(&samples))
+    }
+
+    // Returns a copy of the wave with every harmonic above `max_harmonic` removed, given the wave's
+    // spectrum from `Wave::spectrum`.
+    fn band_limited(spectrum: &[(f64, f64)], max_harmonic: usize, dft: &Dft) -> Self {
+        let n = WAVE_SAMPLES;
+        assert!(2 * max_harmonic < n);
+        // Keep each harmonic and its mirror image, so the result is real.
+        let mut kept = vec![(0.0, 0.0); n];
+        kept[0] = spectrum[0];
+        for k in 1..(max_harmonic + 1) {
+            kept[k] = spectrum[k];
+            kept[n - k] = spectrum[n - k];
+        }
+        let mut samples = [0.0; WAVE_SAMPLES];
+        for (sample, value) in samples.iter_mut().zip(dft.inverse(&kept)) {
+            *sample = (value.0 / n as f64) as f32;
+        }
+        Self { samples }
+    }
+
+    fn read(&self, position: f32, interpolation: Interpolation) -> f32 {
+        let n = WAVE_SAMPLES;
+        let index = position.floor() as usize % n;
+        let t = position - position.floor();
+        let at = |offset: usize| self.samples[(index + offset) % n];
+
+        match interpolation {
+            Interpolation::Truncate => at(0),
+            Interpolation::Linear => at(0) + (at(1) - at(0)) * t,
+            Interpolation::Cubic => {
+                let (y0, y1, y2, y3) = (at(n - 1), at(0), at(1), at(2));
+                let a = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
+                let b = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+                let c = -0.5 * y0 + 0.5 * y2;
+                ((a * t + b) * t + c) * t + y1
+            }
+        }
+    }
+
+    // The wave's discrete Fourier transform, indexed by harmonic.
+    fn spectrum(&self, dft: &Dft) -> Vec<(f64, f64)> {
+        let samples: Vec<(f64, f64)> = self.samples.iter().map(|&sample| (sample as f64, 0.0)).collect();
+        dft.forward(&samples)
+    }
+}
+
+// Each wave is stored once per octave with the harmonics that would alias in that octave removed.
+// Level 0 is the original wave, level L keeps (WAVE_SAMPLES / 2) >> L harmonics.
+const MIPMAP_LEVELS : usize = 10;
+
+#[derive(Clone, Copy)]
+pub enum Interpolation {
+    // Reads the sample before the oscillator's position, like a naive table lookup.
+    Truncate,
+    Linear,
+    // Catmull-Rom interpolation through the two samples on either side.
+    Cubic
 }
 
 #[derive(Clone)]
 pub struct WaveTable {
-    waves: Vec<Wave>
-    // TODO: ensure that waves.len() == WAVE_SAMPLES
+    waves: Vec<Wave>,
+    mipmaps: Rc<Vec<Vec<Wave>>>,
+    interpolation: Interpolation
 }
 
 impl WaveTable {
     pub fn new(waves: Vec<Wave>) -> Self {
-        assert!(waves.len() >= 1);
-        Self { waves }
+        Self::with_interpolation(waves, Interpolation::Linear)
+    }
+
+    pub fn try_new(waves: Vec<Wave>) -> Result<Self, DawrError> {
+        Self::try_with_interpolation(waves, Interpolation::Linear)
+    }
+
+    // Builds `frames` waves from `f(position, phase)`, where position goes from 0.0 for the first
+    // wave to 1.0 for the last, and phase is in [0, 1) across each cycle.
+    pub fn from_fn<F: Fn(f32, f32) -> f32>(frames: usize, f: F, normalize: bool) -> Self {
+        assert!(frames >= 1);
+        let mut waves = Vec::<Wave>::new();
+        for frame in 0..frames {
+            let position = if frames > 1 { frame as f32 / (frames - 1) as f32 } else { 0.0 };
+            waves.push(Wave::from_fn(|phase| f(position, phase), normalize));
+        }
+        Self::new(waves)
+    }
+
+    // Loads a wavetable WAV file made of consecutive single-cycle frames of `frame_size` samples
+    // each, like the 2048-sample frames Serum uses. A partial frame at the end is ignored.
+    pub fn from_wav(filename: &str, frame_size: usize) -> Self {
+        Self::try_from_wav(filename, frame_size).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_from_wav(filename: &str, frame_size: usize) -> Result<Self, DawrError> {
+        let samples = try!(files::try_load_wav_to_mono(filename));
+        if frame_size == 0 || samples.len() < frame_size {
+            return Err(DawrError::InvalidArgument(format!(
+                "{} has {} samples, which isn't enough for a frame of {}", filename, samples.len(), frame_size
+            )));
+        }
+        let (input_dft, output_dft) = (Dft::new(frame_size), Dft::new(WAVE_SAMPLES));
+        Self::try_new(samples.chunks(frame_size)
+            .filter(|frame| frame.len() == frame_size)
+            .map(|frame| Wave::resample(frame, &input_dft, &output_dft))
+            .collect())
+    }
+
+    pub fn with_interpolation(waves: Vec<Wave>, interpolation: Interpolation) -> Self {
+        Self::try_with_interpolation(waves, interpolation).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_with_interpolation(waves: Vec<Wave>, interpolation: Interpolation) -> Result<Self, DawrError> {
+        if waves.len() == 0 {
+            return Err(DawrError::InvalidArgument("a wavetable needs at least one wave".to_string()));
+        }
+        let dft = Dft::new(WAVE_SAMPLES);
+        let mut mipmaps = Vec::<Vec<Wave>>::new();
+        for wave in waves.iter() {
+            let spectrum = wave.spectrum(&dft);
+            let mut levels = vec![wave.clone()];
+            for level in 1..MIPMAP_LEVELS {
+                levels.push(Wave::band_limited(&spectrum, (WAVE_SAMPLES / 2) >> level, &dft));
+            }
+            mipmaps.push(levels);
+        }
+        Ok(Self { waves, mipmaps: Rc::new(mipmaps), interpolation })
+    }
+
+    // Picks the version of a wave that won't alias when the oscillator moves `increment` samples
+    // through it per output sample. Harmonic k aliases once k * increment > WAVE_SAMPLES / 2.
+    fn band_limited(&self, wave: usize, increment: f32) -> &Wave {
+        let level = if increment <= 1.0 {
+            0
+        } else {
+            (increment.log2().ceil() as usize).min(MIPMAP_LEVELS - 1)
+        };
+        &self.mipmaps[wave][level]
+    }
+
+    // `wavetable_position` in [0, 1] crossfades between adjacent waves, so e.g. with 3 waves 0.25
+    // is an even mix of the first two. `wave_position` is in [0, WAVE_SAMPLES).
+    fn sample(&self, wavetable_position: f32, wave_position: f32, increment: f32) -> f32 {
+        let index = wavetable_position.max(0.0).min(1.0) * (self.waves.len() - 1) as f32;
+        let first = index.floor() as usize;
+        let fraction = index - first as f32;
+
+        let sample = self.band_limited(first, increment).read(wave_position, self.interpolation);
+        if fraction > 0.0 {
+            let next = self.band_limited(first + 1, increment).read(wave_position, self.interpolation);
+            sample + (next - sample) * fraction
+        } else {
+            sample
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum EnvelopeCurve {
+    Linear,
+    // Fast at the start of the stage and slow towards the end, like an analog RC envelope.
+    Exponential
+}
+
+impl EnvelopeCurve {
+    // Maps progress through a stage in [0, 1] to the fraction of the way to the stage's target level.
+    fn shape(&self, progress: f32) -> f32 {
+        match *self {
+            EnvelopeCurve::Linear => progress,
+            EnvelopeCurve::Exponential => {
+                const STEEPNESS : f32 = 5.0;
+                (1.0 - (-STEEPNESS * progress).exp()) / (1.0 - (-STEEPNESS).exp())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct EnvelopeCurves {
+    pub attack: EnvelopeCurve,
+    pub decay: EnvelopeCurve,
+    pub release: EnvelopeCurve
+}
+
+impl EnvelopeCurves {
+    pub fn linear() -> Self {
+        Self { attack: EnvelopeCurve::Linear, decay: EnvelopeCurve::Linear, release: EnvelopeCurve::Linear }
+    }
+
+    pub fn exponential() -> Self {
+        Self { attack: EnvelopeCurve::Exponential, decay: EnvelopeCurve::Exponential, release: EnvelopeCurve::Exponential }
     }
 }
 
 pub struct Envelope {
     device: MonoStateContainer<EnvelopeState>,
-    // TODO: attack and release
-    note_events: Rc<EventSource<NoteEvent>>
+    note_events: Rc<EventSource<NoteEvent>>,
+    // Stage times in seconds.
+    attack: Rc<MonoEmitter>,
+    decay: Rc<MonoEmitter>,
+    release: Rc<MonoEmitter>,
+    // Values in [0, 1].
+    sustain: Rc<MonoEmitter>,
+    curves: EnvelopeCurves
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EnvelopeStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release
 }
 
 struct EnvelopeState {
-    on: bool
+    stage: EnvelopeStage,
+    // Progress through the current stage, in [0, 1].
+    progress: f32,
+    // The level the current stage started from.
+    start_level: f32,
+    level: f32
 }
 
 impl Envelope {
-    pub fn new (clock: Rc<Clock>, note_events: Rc<EventSource<NoteEvent>>) -> Rc<Self> {
-        Rc::new(Self { device: MonoStateContainer::<EnvelopeState>::new(clock, EnvelopeState { on: false }), note_events })
+    // A plain gate: 1.0 while a note is playing, 0.0 otherwise.
+    pub fn new(clock: Rc<Clock>, note_events: Rc<EventSource<NoteEvent>>) -> Rc<Self> {
+        Self::adsr(
+            clock.clone(),
+            note_events,
+            ConstSignal::new(clock.clone(), 0.0),
+            ConstSignal::new(clock.clone(), 0.0),
+            ConstSignal::new(clock.clone(), 1.0),
+            ConstSignal::new(clock.clone(), 0.0),
+            EnvelopeCurves::linear()
+        )
+    }
+
+    // A NoteOn restarts the attack from the current level (e.g. part-way through a release), so
+    // retriggering a note never jumps back down to zero.
+    pub fn adsr(
+        clock: Rc<Clock>,
+        note_events: Rc<EventSource<NoteEvent>>,
+        attack: Rc<MonoEmitter>,
+        decay: Rc<MonoEmitter>,
+        sustain: Rc<MonoEmitter>,
+        release: Rc<MonoEmitter>,
+        curves: EnvelopeCurves
+    ) -> Rc<Self> {
+        let state = EnvelopeState { stage: EnvelopeStage::Idle, progress: 0.0, start_level: 0.0, level: 0.0 };
+        Rc::new(Self {
+            device: MonoStateContainer::<EnvelopeState>::new(clock, state),
+            note_events,
+            attack,
+            decay,
+            release,
+            sustain,
+            curves
+        })
+    }
+}
+
+impl EnvelopeState {
+    fn enter(&mut self, stage: EnvelopeStage) {
+        self.stage = stage;
+        self.progress = 0.0;
+        self.start_level = self.level;
+    }
+
+    // Advances progress through the current stage by one sample, returning true once it's over.
+    fn advance(&mut self, seconds: f32, sample_rate: u32) -> bool {
+        if seconds > 0.0 {
+            self.progress += 1.0 / (seconds * sample_rate as f32);
+        } else {
+            self.progress = 1.0;
+        }
+        self.progress >= 1.0
     }
 }
 
@@ -93,26 +436,64 @@ impl MonoEmitter for Envelope {
             let mut cursor = 0;
 
             let mut chunk = self.device.borrow_to_modify();
+            let attack = self.attack.output();
+            let decay = self.decay.output();
+            let sustain = self.sustain.output();
+            let release = self.release.output();
             let mut state = self.device.borrow_state_mut();
-
+            let sample_rate = self.device.sample_rate();
 
             for i in 0..chunk.len() {
                 while cursor < events.len() && events[cursor].0 == self.device.time() + i as u64 {
                     match events[cursor].1 {
                         NoteEvent::NoteOff => {
-                            state.on = false;
+                            if state.stage != EnvelopeStage::Idle {
+                                state.enter(EnvelopeStage::Release);
+                            }
                         },
                         NoteEvent::NoteOn(_freq) => {
-                            state.on = true;
+                            state.enter(EnvelopeStage::Attack);
                         }
                     }
                     cursor += 1
                 }
-                if state.on {
-                    chunk[i] = 1.0;
-                } else {
-                    chunk[i] = 0.0;
+
+                match state.stage {
+                    EnvelopeStage::Idle => {
+                        state.level = 0.0;
+                    },
+                    EnvelopeStage::Attack => {
+                        if state.advance(attack[i], sample_rate) {
+                            state.level = 1.0;
+                            state.enter(EnvelopeStage::Decay);
+                        } else {
+                            let shape = self.curves.attack.shape(state.progress);
+                            state.level = state.start_level + (1.0 - state.start_level) * shape;
+                        }
+                    },
+                    EnvelopeStage::Decay => {
+                        if state.advance(decay[i], sample_rate) {
+                            state.level = sustain[i];
+                            state.stage = EnvelopeStage::Sustain;
+                        } else {
+                            let shape = self.curves.decay.shape(state.progress);
+                            state.level = state.start_level + (sustain[i] - state.start_level) * shape;
+                        }
+                    },
+                    EnvelopeStage::Sustain => {
+                        state.level = sustain[i];
+                    },
+                    EnvelopeStage::Release => {
+                        if state.advance(release[i], sample_rate) {
+                            state.level = 0.0;
+                            state.stage = EnvelopeStage::Idle;
+                        } else {
+                            let shape = self.curves.release.shape(state.progress);
+                            state.level = state.start_level * (1.0 - shape);
+                        }
+                    }
                 }
+                chunk[i] = state.level;
             }
         }
         self.device.borrow_output()
@@ -127,17 +508,27 @@ pub struct Oscillator {
 
 struct OscillatorState {
     position: f32,
-    frequency: f32
+    frequency: f32,
+    // How far the position moves after each sample of the current chunk.
+    increments: Vec<f32>
 }
 
 impl Oscillator {
     pub fn new(clock: Rc<Clock>, note_events: Rc<EventSource<NoteEvent>>, detune_multiplier: Rc<MonoEmitter>) -> Rc<Self> {
         Rc::new(Self {
-            device: MonoStateContainer::<OscillatorState>::new(clock, OscillatorState { position: 0.0, frequency: 0.0 }),
+            device: MonoStateContainer::<OscillatorState>::new(clock, OscillatorState { position: 0.0, frequency: 0.0, increments: Vec::new() }),
             note_events,
             detune_multiplier
         })
     }
+
+    // How many wave samples the position moves per output sample this chunk, worked out from the
+    // note's frequency, so a MonoSynth can pick a band-limited wave without being thrown off by
+    // the phase jumping at each NoteOn.
+    pub fn increments(&self) -> Ref<Vec<f32>> {
+        self.output();
+        Ref::map(self.device.borrow_state(), |state| &state.increments)
+    }
 }
 
 
@@ -152,6 +543,7 @@ impl MonoEmitter for Oscillator {
             let mut chunk = self.device.borrow_to_modify();
             let multipliers = self.detune_multiplier.output();
             let mut state = self.device.borrow_state_mut();
+            state.increments.resize(chunk.len(), 0.0);
 
             for i in 0..chunk.len() {
                 while cursor < events.len() && events[cursor].0 == self.device.time() + i as u64 {
@@ -169,8 +561,9 @@ impl MonoEmitter for Oscillator {
 
                 chunk[i] = state.position;
                 // XXX: possible floating point inaccuracy over the long term
-                let increment = WAVE_SAMPLES as f32 * state.frequency * multipliers[i] / (consts::SAMPLE_RATE as f32);
+                let increment = WAVE_SAMPLES as f32 * state.frequency * multipliers[i] / (self.device.sample_rate() as f32);
                 state.position = (state.position + increment) % (WAVE_SAMPLES as f32);
+                state.increments[i] = increment;
 
             }
 
@@ -179,23 +572,148 @@ impl MonoEmitter for Oscillator {
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    // Rising.
+    Saw,
+    Square,
+    // A new random value every cycle.
+    SampleAndHold
+}
+
+// A low-frequency oscillator for modulation. Outputs offset + depth * wave, where wave is in
+// [-1, 1]. For a tempo-synced rate use TimeCalculator::frequency(), e.g.
+// TimeCalculator::new(160.0).add_quarters(1.0).frequency() for one cycle per beat.
+pub struct Lfo {
+    device: MonoStateContainer<LfoState>,
+    shape: LfoShape,
+    // In Hz.
+    rate: Rc<MonoEmitter>,
+    // Values in [0, 1), where the cycle starts and restarts on retrigger.
+    phase_offset: f32,
+    depth: Rc<MonoEmitter>,
+    offset: Rc<MonoEmitter>,
+    // If set, every NoteOn restarts the cycle.
+    retrigger: Option<Rc<EventSource<NoteEvent>>>
+}
+
+struct LfoState {
+    // Values in [0, 1).
+    phase: f32,
+    held: f32
+}
+
+impl Lfo {
+    pub fn new(
+        clock: Rc<Clock>,
+        shape: LfoShape,
+        rate: Rc<MonoEmitter>,
+        phase_offset: f32,
+        depth: Rc<MonoEmitter>,
+        offset: Rc<MonoEmitter>,
+        retrigger: Option<Rc<EventSource<NoteEvent>>>
+    ) -> Rc<Self> {
+        let phase_offset = phase_offset.rem_euclid(1.0);
+        let state = LfoState { phase: phase_offset, held: 1.0 - 2.0 * rand::random::<f32>() };
+        Rc::new(Self {
+            device: MonoStateContainer::new(clock, state),
+            shape,
+            rate,
+            phase_offset,
+            depth,
+            offset,
+            retrigger
+        })
+    }
+}
+
+impl MonoEmitter for Lfo {
+    fn output(&self) -> Ref<Vec<f32>> {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let events : &[(u64, NoteEvent)] = match self.retrigger {
+                Some(ref events) => events.events_this_chunk(),
+                None => &[]
+            };
+            let mut cursor = 0;
+
+            let mut chunk = self.device.borrow_to_modify();
+            let rate = self.rate.output();
+            let depth = self.depth.output();
+            let offset = self.offset.output();
+            let mut state = self.device.borrow_state_mut();
+
+            for i in 0..chunk.len() {
+                while cursor < events.len() && events[cursor].0 == self.device.time() + i as u64 {
+                    if let NoteEvent::NoteOn(_freq) = events[cursor].1 {
+                        state.phase = self.phase_offset;
+                        state.held = 1.0 - 2.0 * rand::random::<f32>();
+                    }
+                    cursor += 1
+                }
+
+                let p = state.phase;
+                let wave = match self.shape {
+                    LfoShape::Sine => (2.0 * PI as f32 * p).sin(),
+                    LfoShape::Triangle => 1.0 - 4.0 * (p - 0.5).abs(),
+                    LfoShape::Saw => 2.0 * p - 1.0,
+                    LfoShape::Square => if p < 0.5 { 1.0 } else { -1.0 },
+                    LfoShape::SampleAndHold => state.held
+                };
+                chunk[i] = offset[i] + depth[i] * wave;
+
+                state.phase += rate[i] / self.device.sample_rate() as f32;
+                if state.phase >= 1.0 || state.phase < 0.0 {
+                    state.phase = state.phase.rem_euclid(1.0);
+                    state.held = 1.0 - 2.0 * rand::random::<f32>();
+                }
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
 pub struct MonoSynth {
-    device: MonoStateContainer<()>,
+    device: MonoStateContainer<MonoSynthState>,
     wavetable: WaveTable,
     // Values in [0, WAVE_SAMPLES)
     oscillator: Rc<MonoEmitter>,
+    // Set when the phase comes from an Oscillator, which knows how fast it's moving.
+    increments: Option<Rc<Oscillator>>,
     // Values in [0, 1], 0 being the first wave, 1 being the last.
     wavetable_position: Rc<MonoEmitter>,
     // Values in [0, 1].
     envelope: Rc<MonoEmitter>,
 }
 
+struct MonoSynthState {
+    // Used to work out how fast a phase source other than an Oscillator is moving.
+    last_wave_position: f32
+}
+
 impl MonoSynth {
+    // Takes any phase source. The band-limited wave is picked from how far the phase moved since
+    // the last sample, so a jump in phase picks the wrong one for that sample. Use with_oscillator
+    // when the phase comes from an Oscillator.
     pub fn new(clock: Rc<Clock>, wavetable: WaveTable, oscillator: Rc<MonoEmitter>, wavetable_position: Rc<MonoEmitter>, envelope: Rc<MonoEmitter>) -> Rc<Self> {
+        Self::with_increments(clock, wavetable, oscillator, None, wavetable_position, envelope)
+    }
+
+    // Picks the band-limited wave from the Oscillator's frequency, so the random phase at each
+    // NoteOn doesn't throw it off.
+    pub fn with_oscillator(clock: Rc<Clock>, wavetable: WaveTable, oscillator: Rc<Oscillator>, wavetable_position: Rc<MonoEmitter>, envelope: Rc<MonoEmitter>) -> Rc<Self> {
+        Self::with_increments(clock, wavetable, oscillator.clone(), Some(oscillator), wavetable_position, envelope)
+    }
+
+    fn with_increments(clock: Rc<Clock>, wavetable: WaveTable, oscillator: Rc<MonoEmitter>, increments: Option<Rc<Oscillator>>, wavetable_position: Rc<MonoEmitter>, envelope: Rc<MonoEmitter>) -> Rc<Self> {
         Rc::new(Self {
-            device: MonoStateContainer::<()>::new(clock, ()),
+            device: MonoStateContainer::<MonoSynthState>::new(clock, MonoSynthState { last_wave_position: 0.0 }),
             wavetable,
             oscillator,
+            increments,
             wavetable_position,
             envelope
         })
@@ -210,17 +728,25 @@ impl MonoEmitter for MonoSynth {
             let mut output = self.device.borrow_to_modify();
 
             let wave_position = self.oscillator.output();
+            let increments = self.increments.as_ref().map(|oscillator| oscillator.increments());
             let wavetable_position = self.wavetable_position.output();
             let amplitude = self.envelope.output();
+            let mut state = self.device.borrow_state_mut();
 
-            for i in 0..consts::CHUNK_SIZE {
-                // For e.g. 10 waves we want it to work like:
-                //  0: [0, 0.1)
-                //  1: [0.1, 0.2),
-                //  ...
-                //  9: [0.9, 1.0] <-- this square bracket is the reason for the "max" below.
-                let mut wt_index = (wavetable_position[i].max(0.99999) * self.wavetable.waves.len() as f32).floor() as usize;
-                output[i] = amplitude[i] * self.wavetable.waves[wt_index].samples[wave_position[i] as usize];
+            for i in 0..self.device.chunk_size() {
+                let increment = match increments {
+                    Some(ref increments) => increments[i],
+                    None => {
+                        let mut increment = wave_position[i] - state.last_wave_position;
+                        if increment < 0.0 {
+                            increment += WAVE_SAMPLES as f32;
+                        }
+                        increment
+                    }
+                };
+                state.last_wave_position = wave_position[i];
+
+                output[i] = amplitude[i] * self.wavetable.sample(wavetable_position[i], wave_position[i], increment);
             }
         }
         self.device.borrow_output()
@@ -228,6 +754,160 @@ impl MonoEmitter for MonoSynth {
 }
 
 
+// How PolySynth picks a voice to cut off when every voice is holding a note. Voices that have
+// never played come first, then the one that was released the longest time ago, so a held note is
+// only stolen when there's no free voice. Voices are assigned before anything is rendered, so
+// there's no way to steal by envelope level.
+pub enum VoiceStealing {
+    // Steal the voice whose note started the longest time ago.
+    Oldest
+}
+
+struct VoiceAllocation {
+    events: Vec<(u64, NoteEvent)>,
+    held_note: Option<u32>,
+    started: Option<u64>,
+    released: Option<u64>
+}
+
+pub struct PolySynth {
+    device: StereoStateContainer<()>,
+    voices: Vec<Rc<MonoEmitter>>
+}
+
+impl PolySynth {
+    // Every voice is an Oscillator, envelope and MonoSynth chain. The envelope is built by calling
+    // `envelope` with the voice's own note events, e.g. to create an Envelope::adsr per voice.
+    // Voices are assigned up front from the whole event list, so stealing is decided from note
+    // timing alone.
+    pub fn new(
+        clock: Rc<Clock>,
+        note_events: Rc<EventSource<PolyNoteEvent>>,
+        voices: usize,
+        stealing: VoiceStealing,
+        wavetable: WaveTable,
+        detune_multiplier: Rc<MonoEmitter>,
+        wavetable_position: Rc<MonoEmitter>,
+        envelope: &Fn(Rc<EventSource<NoteEvent>>) -> Rc<MonoEmitter>
+    ) -> Rc<Self> {
+        Self::try_new(clock, note_events, voices, stealing, wavetable, detune_multiplier, wavetable_position, envelope)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(
+        clock: Rc<Clock>,
+        note_events: Rc<EventSource<PolyNoteEvent>>,
+        voices: usize,
+        stealing: VoiceStealing,
+        wavetable: WaveTable,
+        detune_multiplier: Rc<MonoEmitter>,
+        wavetable_position: Rc<MonoEmitter>,
+        envelope: &Fn(Rc<EventSource<NoteEvent>>) -> Rc<MonoEmitter>
+    ) -> Result<Rc<Self>, DawrError> {
+        if voices == 0 {
+            return Err(DawrError::InvalidArgument("A PolySynth needs at least one voice".to_string()));
+        }
+
+        let allocations = Self::allocate_voices(note_events.all_events(), voices, stealing);
+
+        let mut synths = Vec::<Rc<MonoEmitter>>::new();
+        for allocation in allocations {
+            let es = EventSource::new(allocation.events, clock.clone());
+            synths.push(MonoSynth::with_oscillator(
+                clock.clone(),
+                wavetable.clone(),
+                Oscillator::new(clock.clone(), es.clone(), detune_multiplier.clone()),
+                wavetable_position.clone(),
+                envelope(es)
+            ));
+        }
+
+        Ok(Rc::new(Self {
+            device: StereoStateContainer::<()>::new(clock, ()),
+            voices: synths
+        }))
+    }
+
+    fn allocate_voices(note_events: &[(u64, PolyNoteEvent)], voices: usize, stealing: VoiceStealing) -> Vec<VoiceAllocation> {
+        let mut allocations = Vec::<VoiceAllocation>::new();
+        for _ in 0..voices {
+            allocations.push(VoiceAllocation { events: Vec::new(), held_note: None, started: None, released: None });
+        }
+
+        for &(time, ref event) in note_events {
+            match *event {
+                PolyNoteEvent::NoteOn(id, freq) => {
+                    // A NoteOn for a note that's already held retriggers its voice, so a single
+                    // NoteOff still releases it.
+                    let voice = match allocations.iter().position(|a| a.held_note == Some(id)) {
+                        Some(voice) => voice,
+                        None => Self::choose_voice(&allocations, &stealing)
+                    };
+                    let allocation = &mut allocations[voice];
+                    allocation.events.push((time, NoteEvent::NoteOn(freq)));
+                    allocation.held_note = Some(id);
+                    allocation.started = Some(time);
+                    allocation.released = None;
+                },
+                PolyNoteEvent::NoteOff(id) => {
+                    // If the note's voice was stolen there's nothing left to release.
+                    if let Some(allocation) = allocations.iter_mut().find(|a| a.held_note == Some(id)) {
+                        allocation.events.push((time, NoteEvent::NoteOff));
+                        allocation.held_note = None;
+                        allocation.released = Some(time);
+                    }
+                }
+            }
+        }
+
+        allocations
+    }
+
+    fn choose_voice(allocations: &[VoiceAllocation], stealing: &VoiceStealing) -> usize {
+        if let Some(unused) = (0..allocations.len()).find(|&v| allocations[v].started.is_none()) {
+            return unused;
+        }
+        let released = (0..allocations.len())
+            .filter(|&v| allocations[v].held_note.is_none())
+            .min_by_key(|&v| allocations[v].released);
+        if let Some(released) = released {
+            return released;
+        }
+
+        // Every voice is holding a note, so one of them has to be cut off.
+        match *stealing {
+            VoiceStealing::Oldest => (0..allocations.len()).min_by_key(|&v| allocations[v].started).unwrap()
+        }
+    }
+}
+
+impl StereoEmitter for PolySynth {
+    fn output(&self) -> (Ref<Vec<f32>>, Ref<Vec<f32>>) {
+        if self.device.clock_advanced() {
+            self.device.mark_as_up_to_date();
+
+            let mut left = self.device.borrow_left_to_modify();
+            let mut right = self.device.borrow_right_to_modify();
+
+            for i in 0..self.device.chunk_size() {
+                left[i] = 0.0;
+            }
+
+            for voice in self.voices.iter() {
+                let output = voice.output();
+                for i in 0..self.device.chunk_size() {
+                    left[i] += output[i];
+                }
+            }
+
+            for i in 0..self.device.chunk_size() {
+                right[i] = left[i];
+            }
+        }
+        self.device.borrow_output()
+    }
+}
+
 pub struct WhiteNoise {
     device: StereoStateContainer<()>
 }
@@ -248,7 +928,7 @@ impl StereoEmitter for WhiteNoise {
             let mut left = self.device.borrow_left_to_modify();
             let mut right = self.device.borrow_right_to_modify();
 
-            for i in 0..consts::CHUNK_SIZE {
+            for i in 0..self.device.chunk_size() {
                 left[i] = 1.0 - 2.0 * rand::random::<f32>();
                 right[i] = 1.0 - 2.0 * rand::random::<f32>();
             }
@@ -256,3 +936,75 @@ impl StereoEmitter for WhiteNoise {
         self.device.borrow_output()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_ons(allocation: &VoiceAllocation) -> usize {
+        allocation.events.iter().filter(|event| match event.1 { NoteEvent::NoteOn(_) => true, _ => false }).count()
+    }
+
+    fn note_offs(allocation: &VoiceAllocation) -> usize {
+        allocation.events.len() - note_ons(allocation)
+    }
+
+    #[test]
+    fn allocate_voices_reuses_released_voice_before_stealing() {
+        let events = vec![
+            (0, PolyNoteEvent::NoteOn(0, 220.0)),
+            (10, PolyNoteEvent::NoteOn(1, 330.0)),
+            (20, PolyNoteEvent::NoteOff(1)),
+            (30, PolyNoteEvent::NoteOn(2, 440.0))
+        ];
+        let allocations = PolySynth::allocate_voices(&events, 2, VoiceStealing::Oldest);
+        assert_eq!((note_ons(&allocations[0]), note_offs(&allocations[0])), (1, 0));
+        assert_eq!((note_ons(&allocations[1]), note_offs(&allocations[1])), (2, 1));
+    }
+
+    #[test]
+    fn allocate_voices_prefers_unused_then_oldest_release() {
+        let events = vec![
+            (0, PolyNoteEvent::NoteOn(0, 220.0)),
+            (1, PolyNoteEvent::NoteOn(1, 330.0)),
+            (2, PolyNoteEvent::NoteOff(1)),
+            (3, PolyNoteEvent::NoteOff(0)),
+            // Voice 2 has never played, so it's used first.
+            (4, PolyNoteEvent::NoteOn(2, 440.0)),
+            // Then voice 1, which was released before voice 0.
+            (5, PolyNoteEvent::NoteOn(3, 550.0))
+        ];
+        let allocations = PolySynth::allocate_voices(&events, 3, VoiceStealing::Oldest);
+        assert_eq!(allocations[2].held_note, Some(2));
+        assert_eq!(allocations[1].held_note, Some(3));
+        assert_eq!(allocations[0].held_note, None);
+    }
+
+    #[test]
+    fn allocate_voices_retriggers_held_note() {
+        let events = vec![
+            (0, PolyNoteEvent::NoteOn(0, 220.0)),
+            (10, PolyNoteEvent::NoteOn(0, 220.0)),
+            (20, PolyNoteEvent::NoteOff(0))
+        ];
+        let allocations = PolySynth::allocate_voices(&events, 2, VoiceStealing::Oldest);
+        assert_eq!((note_ons(&allocations[0]), note_offs(&allocations[0])), (2, 1));
+        assert!(allocations[1].events.is_empty());
+        assert!(allocations.iter().all(|a| a.held_note.is_none()));
+    }
+
+    #[test]
+    fn allocate_voices_steals_oldest_held_note() {
+        let events = vec![
+            (0, PolyNoteEvent::NoteOn(0, 220.0)),
+            (10, PolyNoteEvent::NoteOn(1, 330.0)),
+            (20, PolyNoteEvent::NoteOn(2, 440.0)),
+            // Note 0 was stolen, so there's nothing to release.
+            (30, PolyNoteEvent::NoteOff(0))
+        ];
+        let allocations = PolySynth::allocate_voices(&events, 2, VoiceStealing::Oldest);
+        assert_eq!(allocations[0].held_note, Some(2));
+        assert_eq!(allocations[1].held_note, Some(1));
+        assert_eq!(note_offs(&allocations[0]) + note_offs(&allocations[1]), 0);
+    }
+}